@@ -0,0 +1,384 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::{Algorithm2D, Point};
+use specs::prelude::*;
+use specs::shred::ResourceId;
+
+use crate::{
+    components::{
+        CombatStats, Effect, Equippable, Equipped, InBackpack, Item, Name, Position, Potion, Ranged,
+        StatusEffects, Throwable, Viewshed, WantsToDrinkPotion, WantsToDropItem, WantsToEquip, WantsToMelee,
+        WantsToPickupItem, WantsToShoot,
+    },
+    gamelog::GameLog,
+    map::{Map, TileMeta, TileType},
+    rng::StatusRng,
+    targeting::can_fire,
+};
+
+// Everything an actor can do with its turn. Keys, the AI and anything
+// else that plays the game produce these, and only ActionResolver
+// decides what they actually do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    // One step, walking into an enemy attacks it
+    Move { dx: i32, dy: i32 },
+    Attack(Entity),
+    Wait,
+    PickUp,
+    // Both only for items the actor carries
+    Drink(Entity),
+    Drop(Entity),
+    Equip(Entity),
+    // `with` is a worn ranged weapon or something carried to throw
+    Shoot { with: Entity, target: Entity },
+    UseStairs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActionResult {
+    // The action happened and used up the turn
+    Done,
+    // Nothing happened, so no time passes
+    Nothing,
+    Descend,
+    Ascend,
+}
+
+type ResolverData<'a> = (
+    Entities<'a>,
+    ReadExpect<'a, Entity>,
+    WriteExpect<'a, Point>,
+    WriteExpect<'a, Map>,
+    WriteExpect<'a, GameLog>,
+    WriteStorage<'a, Position>,
+    WriteStorage<'a, Viewshed>,
+    ReadStorage<'a, CombatStats>,
+    WriteStorage<'a, WantsToMelee>,
+    ReadStorage<'a, Name>,
+    ReadStorage<'a, Item>,
+    ReadStorage<'a, Potion>,
+    ReadStorage<'a, InBackpack>,
+    WriteStorage<'a, WantsToPickupItem>,
+    WriteStorage<'a, WantsToDrinkPotion>,
+    WriteStorage<'a, WantsToDropItem>,
+    ReadStorage<'a, Equippable>,
+    WriteStorage<'a, WantsToEquip>,
+    ReadStorage<'a, Equipped>,
+    ReadStorage<'a, Ranged>,
+    ReadStorage<'a, Throwable>,
+    WriteStorage<'a, WantsToShoot>,
+    ReadStorage<'a, StatusEffects>,
+    WriteExpect<'a, StatusRng>,
+);
+
+// The rules of the game, in one place. Fetch it from the World for the
+// player, or put it in a system's data for everyone else.
+pub struct ActionResolver<'a> {
+    pub entities: Entities<'a>,
+    pub player: ReadExpect<'a, Entity>,
+    pub player_pos: WriteExpect<'a, Point>,
+    pub map: WriteExpect<'a, Map>,
+    pub gamelog: WriteExpect<'a, GameLog>,
+    pub positions: WriteStorage<'a, Position>,
+    pub viewsheds: WriteStorage<'a, Viewshed>,
+    pub combat_stats: ReadStorage<'a, CombatStats>,
+    pub wants_to_melee: WriteStorage<'a, WantsToMelee>,
+    pub names: ReadStorage<'a, Name>,
+    pub items: ReadStorage<'a, Item>,
+    pub potions: ReadStorage<'a, Potion>,
+    pub backpack: ReadStorage<'a, InBackpack>,
+    pub wants_pickup: WriteStorage<'a, WantsToPickupItem>,
+    pub wants_drink: WriteStorage<'a, WantsToDrinkPotion>,
+    pub wants_drop: WriteStorage<'a, WantsToDropItem>,
+    pub equippable: ReadStorage<'a, Equippable>,
+    pub wants_equip: WriteStorage<'a, WantsToEquip>,
+    pub equipped: ReadStorage<'a, Equipped>,
+    pub ranged: ReadStorage<'a, Ranged>,
+    pub throwable: ReadStorage<'a, Throwable>,
+    pub wants_shoot: WriteStorage<'a, WantsToShoot>,
+    pub statuses: ReadStorage<'a, StatusEffects>,
+    pub status_rng: WriteExpect<'a, StatusRng>,
+}
+
+impl<'a> SystemData<'a> for ActionResolver<'a> {
+    fn setup(world: &mut World) {
+        ResolverData::setup(world)
+    }
+
+    fn fetch(world: &'a World) -> Self {
+        let (
+            entities,
+            player,
+            player_pos,
+            map,
+            gamelog,
+            positions,
+            viewsheds,
+            combat_stats,
+            wants_to_melee,
+            names,
+            items,
+            potions,
+            backpack,
+            wants_pickup,
+            wants_drink,
+            wants_drop,
+            equippable,
+            wants_equip,
+            equipped,
+            ranged,
+            throwable,
+            wants_shoot,
+            statuses,
+            status_rng,
+        ) = ResolverData::fetch(world);
+        ActionResolver {
+            entities,
+            player,
+            player_pos,
+            map,
+            gamelog,
+            positions,
+            viewsheds,
+            combat_stats,
+            wants_to_melee,
+            names,
+            items,
+            potions,
+            backpack,
+            wants_pickup,
+            wants_drink,
+            wants_drop,
+            equippable,
+            wants_equip,
+            equipped,
+            ranged,
+            throwable,
+            wants_shoot,
+            statuses,
+            status_rng,
+        }
+    }
+
+    fn reads() -> Vec<ResourceId> {
+        ResolverData::reads()
+    }
+
+    fn writes() -> Vec<ResourceId> {
+        ResolverData::writes()
+    }
+}
+
+impl<'a> ActionResolver<'a> {
+    pub fn resolve(&mut self, actor: Entity, action: Action) -> ActionResult {
+        let Some(from) = self.positions.get(actor).map(|p| p.point) else {
+            return ActionResult::Nothing;
+        };
+        match action {
+            Action::Move { .. } if self.has(actor, Effect::Confusion) => {
+                let dx = self.status_rng.0.range(-1, 2);
+                let dy = self.status_rng.0.range(-1, 2);
+                // Stumbling into a wall still takes the turn
+                if self.move_by(actor, from, dx, dy) == ActionResult::Nothing {
+                    self.tell(actor, "You stumble about in confusion.");
+                }
+                ActionResult::Done
+            }
+            Action::Move { dx, dy } => self.move_by(actor, from, dx, dy),
+            Action::Attack(target) => self.attack(actor, from, target),
+            Action::Wait => ActionResult::Done,
+            Action::PickUp => self.pick_up(actor, from),
+            Action::Drink(item) => {
+                if !self.carries(actor, item) || !self.potions.contains(item) {
+                    self.tell(actor, "You can't drink that.");
+                    return ActionResult::Nothing;
+                }
+                self.wants_drink
+                    .insert(actor, WantsToDrinkPotion { potion: item })
+                    .expect("Unable to insert drink");
+                ActionResult::Done
+            }
+            Action::Drop(item) => {
+                if !self.carries(actor, item) {
+                    return ActionResult::Nothing;
+                }
+                self.wants_drop
+                    .insert(actor, WantsToDropItem { item })
+                    .expect("Unable to insert drop");
+                ActionResult::Done
+            }
+            Action::Equip(item) => {
+                if !self.carries(actor, item) || !self.equippable.contains(item) {
+                    self.tell(actor, "You can't wear that.");
+                    return ActionResult::Nothing;
+                }
+                self.wants_equip
+                    .insert(actor, WantsToEquip { item })
+                    .expect("Unable to insert equip");
+                ActionResult::Done
+            }
+            Action::Shoot { with, target } => self.shoot(actor, from, with, target),
+            Action::UseStairs => {
+                let idx = self.map.point2d_to_index(from);
+                match self.map.tiles[idx] {
+                    // Monsters stay on their own level
+                    _ if actor != *self.player => ActionResult::Nothing,
+                    TileType::DownStairs => ActionResult::Descend,
+                    TileType::UpStairs => ActionResult::Ascend,
+                    _ => {
+                        self.tell(actor, "There are no stairs here.");
+                        ActionResult::Nothing
+                    }
+                }
+            }
+        }
+    }
+
+    // Explains to the player why nothing happened, monsters are on
+    // their own
+    fn tell(&mut self, actor: Entity, message: &str) {
+        if actor == *self.player {
+            self.gamelog.entries.push(message.to_string());
+        }
+    }
+
+    fn has(&self, actor: Entity, effect: Effect) -> bool {
+        self.statuses.get(actor).is_some_and(|s| s.has(effect))
+    }
+
+    fn carries(&self, actor: Entity, item: Entity) -> bool {
+        self.backpack.get(item).is_some_and(|b| b.owner == actor)
+    }
+
+    // The ranged weapon `actor` has in hand, if any
+    pub fn ranged_weapon(&self, actor: Entity) -> Option<Entity> {
+        (&self.entities, &self.equipped, &self.ranged)
+            .join()
+            .find(|(_, worn, _)| worn.owner == actor)
+            .map(|(item, _, _)| item)
+    }
+
+    fn shoot(&mut self, actor: Entity, from: Point, with: Entity, target: Entity) -> ActionResult {
+        let in_hand = self.equipped.get(with).is_some_and(|w| w.owner == actor);
+        let to_throw = self.carries(actor, with) && self.throwable.contains(with);
+        let Some(range) = self.ranged.get(with).map(|r| r.range).filter(|_| in_hand || to_throw)
+        else {
+            self.tell(actor, "You can't shoot with that.");
+            return ActionResult::Nothing;
+        };
+        let in_sight = self
+            .positions
+            .get(target)
+            .is_some_and(|p| can_fire(&self.map, from, p.point, range));
+        let alive = self.combat_stats.get(target).is_some_and(|s| s.hp > 0);
+        if target == actor || !in_sight || !alive {
+            self.tell(actor, "You can't hit that from here.");
+            return ActionResult::Nothing;
+        }
+        self.wants_shoot
+            .insert(actor, WantsToShoot { target, with })
+            .expect("Unable to insert shot");
+        ActionResult::Done
+    }
+
+    fn pick_up(&mut self, actor: Entity, at: Point) -> ActionResult {
+        let item = (&self.entities, &self.items, &self.positions)
+            .join()
+            .find(|(_, _, pos)| pos.point == at)
+            .map(|(e, _, _)| e);
+        match item {
+            Some(item) => {
+                self.wants_pickup
+                    .insert(actor, WantsToPickupItem { item })
+                    .expect("Unable to insert pickup");
+                ActionResult::Done
+            }
+            None => {
+                self.tell(actor, "There is nothing here to pick up.");
+                ActionResult::Nothing
+            }
+        }
+    }
+
+    // Only the player and the monsters fight, monsters don't bump into
+    // each other on purpose
+    fn hostile(&self, a: Entity, b: Entity) -> bool {
+        a != b && (a == *self.player || b == *self.player)
+    }
+
+    fn move_by(&mut self, actor: Entity, from: Point, dx: i32, dy: i32) -> ActionResult {
+        let to = Point::new(from.x + dx, from.y + dy);
+        if to == from || !self.map.in_bounds(to) {
+            return ActionResult::Nothing;
+        }
+        let idx = self.map.point2d_to_index(to);
+
+        let target = self.map.tile_content[idx]
+            .iter()
+            .copied()
+            .find(|e| self.combat_stats.contains(*e) && self.hostile(actor, *e));
+        if let Some(target) = target {
+            return self.attack(actor, from, target);
+        }
+
+        if self.map.tiles[idx].is_blocker() || self.map.blocked[idx] {
+            return ActionResult::Nothing;
+        }
+
+        let old = self.map.point2d_to_index(from);
+        self.map.blocked[old] = false;
+        self.map.blocked[idx] = true;
+        if let Some(pos) = self.positions.get_mut(actor) {
+            pos.point = to;
+        }
+        if let Some(viewshed) = self.viewsheds.get_mut(actor) {
+            viewshed.dirty = true;
+        }
+        if actor == *self.player {
+            *self.player_pos = to;
+        }
+        ActionResult::Done
+    }
+
+    fn attack(&mut self, actor: Entity, from: Point, target: Entity) -> ActionResult {
+        let in_reach = self
+            .positions
+            .get(target)
+            .map(|p| rltk::DistanceAlg::Pythagoras.distance2d(from, p.point) < 1.5)
+            .unwrap_or(false);
+        if !in_reach || !self.combat_stats.contains(target) || !self.entities.is_alive(target) {
+            return ActionResult::Nothing;
+        }
+        self.wants_to_melee
+            .insert(actor, WantsToMelee { target })
+            .expect("Unable to insert attack");
+        ActionResult::Done
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+    #[test]
+    fn test_moves_walk_attack_or_cost_nothing(seed in any::<u64>(), dx in -1..=1i32, dy in -1..=1i32) {
+        use crate::state::test_state;
+
+        let gs = test_state(seed);
+        let player = *gs.ecs.fetch::<Entity>();
+        let from = *gs.ecs.fetch::<Point>();
+        let result = gs.ecs.system_data::<ActionResolver>().resolve(player, Action::Move { dx, dy });
+
+        let at = gs.ecs.read_storage::<Position>().get(player).unwrap().point;
+        let attacking = gs.ecs.read_storage::<WantsToMelee>().contains(player);
+        match result {
+            ActionResult::Done if attacking => prop_assert_eq!(at, from),
+            ActionResult::Done => {
+                prop_assert_eq!(at, Point::new(from.x + dx, from.y + dy));
+                prop_assert_eq!(*gs.ecs.fetch::<Point>(), at);
+            }
+            ActionResult::Nothing => prop_assert_eq!(at, from),
+            other => prop_assert!(false, "a move ended in {:?}", other),
+        }
+    }
+}
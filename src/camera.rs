@@ -0,0 +1,122 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::{Algorithm2D, FontCharType, Point, Rltk, RGB};
+use specs::prelude::*;
+
+use crate::{
+    components::{Item, Position, Renderable},
+    gui::default_config,
+    map::{Map, TileMeta},
+};
+
+pub struct CameraConfig {
+    pub out_of_bounds_glyph: FontCharType,
+    pub out_of_bounds_fg: RGB,
+}
+
+pub fn default_camera_config() -> CameraConfig {
+    CameraConfig {
+        out_of_bounds_glyph: rltk::to_cp437('·'),
+        out_of_bounds_fg: RGB::named(rltk::DARK_GRAY),
+    }
+}
+
+// The window of the map that is on screen. `min` is the map coordinate
+// drawn at the top left of the console and `size` is how many tiles fit
+// above the UI box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub min: Point,
+    pub size: Point,
+}
+
+impl Viewport {
+    pub fn centred_on(center: Point, size: Point) -> Viewport {
+        Viewport {
+            min: Point::new(center.x - size.x / 2, center.y - size.y / 2),
+            size,
+        }
+    }
+
+    pub fn world_to_screen(&self, p: Point) -> Option<Point> {
+        let screen = Point::new(p.x - self.min.x, p.y - self.min.y);
+        if screen.x < 0 || screen.x >= self.size.x || screen.y < 0 || screen.y >= self.size.y {
+            return None;
+        }
+        Some(screen)
+    }
+
+    pub fn screen_to_world(&self, p: Point) -> Point {
+        Point::new(p.x + self.min.x, p.y + self.min.y)
+    }
+}
+
+pub fn viewport(ecs: &World, ctx: &Rltk) -> Viewport {
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let ui = default_config(screen_width, screen_height);
+    let player_pos = ecs.fetch::<Point>();
+    Viewport::centred_on(*player_pos, Point::new(screen_width as i32, ui.ui_origin.y))
+}
+
+pub fn render_camera(ecs: &World, ctx: &mut Rltk) {
+    let view = viewport(ecs, ctx);
+    let map = ecs.fetch::<Map>();
+    let config = ecs.fetch::<CameraConfig>();
+
+    for sy in 0..view.size.y {
+        for sx in 0..view.size.x {
+            let p = view.screen_to_world(Point::new(sx, sy));
+            if !map.in_bounds(p) {
+                ctx.set(
+                    sx,
+                    sy,
+                    config.out_of_bounds_fg,
+                    RGB::named(rltk::BLACK),
+                    config.out_of_bounds_glyph,
+                );
+                continue;
+            }
+            let idx = map.point2d_to_index(p);
+            // Render a tile depending upon the tile type
+            if map.revealed_tiles[idx] {
+                let tile = map.tiles[idx];
+                let fg = if !map.visible_tiles[idx] {
+                    tile.color().to_greyscale()
+                } else {
+                    tile.color()
+                };
+                ctx.set(sx, sy, fg, RGB::named(rltk::BLACK), tile.glpyh());
+            }
+        }
+    }
+
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let items = ecs.read_storage::<Item>();
+    let mut draw = |pos: &Position, render: &Renderable| {
+        if !map.visible_tiles[map.point2d_to_index(pos.point)] {
+            return;
+        }
+        if let Some(screen) = view.world_to_screen(pos.point) {
+            ctx.set(screen.x, screen.y, render.fg, render.bg, render.glyph);
+        }
+    };
+    // Items go first, so whoever stands on one is drawn over it
+    for (pos, render, _) in (&positions, &renderables, &items).join() {
+        draw(pos, render);
+    }
+    for (pos, render, _) in (&positions, &renderables, !&items).join() {
+        draw(pos, render);
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_viewport_round_trip(cx in -50..250, cy in -50..250, sx in 0..80, sy in 0..43) {
+        let view = Viewport::centred_on(Point::new(cx, cy), Point::new(80, 43));
+        let screen = Point::new(sx, sy);
+        prop_assert_eq!(view.world_to_screen(view.screen_to_world(screen)), Some(screen));
+        prop_assert_eq!(view.world_to_screen(Point::new(cx, cy)), Some(Point::new(40, 21)));
+    }
+}
@@ -1,61 +1,163 @@
 use rltk::{RGB, Point};
+use serde::{Deserialize, Serialize};
+// The ConvertSaveload derive still names the old error type
+#[allow(deprecated)]
+use specs::error::NoError;
 use specs::prelude::*;
-use specs_derive::Component;
+use specs::saveload::{ConvertSaveload, Marker, SimpleMarker, SimpleMarkerAllocator};
+use specs_derive::{Component, ConvertSaveload};
+use std::convert::Infallible;
 
+use crate::monster::MonsterSpec;
+use crate::saveload::SerializeMe;
 
-#[derive(Component, Clone)]
+
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Position {
     pub point : Point
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Renderable {
     pub glyph: rltk::FontCharType,
     pub fg: RGB,
     pub bg: RGB,
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Name {
     pub name: String
 }
-#[derive(Component, Clone)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Viewshed {
     pub visible_tiles: Vec<Point>,
     pub range: i32,
     pub dirty: bool,
 }
-#[derive(Component, Debug)]
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Monster {
     pub mobile: bool,
 }
-#[derive(Component, Debug)]
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Player {}
 
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Serialize, Deserialize, Debug, Clone)]
 pub struct CombatStats {
     pub max_hp: i32,
     pub hp: i32,
     pub defense: i32,
     pub power: i32,
 }
-#[derive(Component, Debug)]
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct BlocksTile {}
 
-#[derive(Component, Debug)]
+// Every tick of the clock adds `speed` to `energy`, and a turn is had
+// whenever there is enough energy saved up to pay for it
+#[derive(Component, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Energy {
+    pub speed: i32,
+    pub energy: i32,
+}
+
+// Given to whoever may act this tick by the InitiativeSystem
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct MyTurn {}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
 pub struct WantsToMelee {
     pub target: Entity,
 }
 
-#[derive(Component, Debug)]
+// One blow, with whoever struck it. A poison tick carries whoever inflicted
+// the poison.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub amount: i32,
+    pub attacker: Option<Entity>,
+}
+
+#[derive(Component, Clone, Debug)]
 pub struct SufferDamage {
-    pub amount: Vec<i32>,
+    pub hits: Vec<Hit>,
+}
+
+// The derive can't see into a Vec, so the attackers are swapped for
+// their markers by hand. One that wasn't saved is forgotten.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SufferDamageData<M> {
+    pub hits: Vec<(i32, Option<M>)>,
 }
 
+impl<M: Marker + Serialize> ConvertSaveload<M> for SufferDamage
+where
+    for<'de> M: Deserialize<'de>,
+{
+    type Data = SufferDamageData<M>;
+    type Error = Infallible;
+
+    fn convert_into<F>(&self, mut ids: F) -> Result<Self::Data, Self::Error>
+    where
+        F: FnMut(Entity) -> Option<M>,
+    {
+        let hits = self
+            .hits
+            .iter()
+            .map(|hit| (hit.amount, hit.attacker.and_then(&mut ids)))
+            .collect();
+        Ok(SufferDamageData { hits })
+    }
 
-#[derive(Component, Debug)]
+    fn convert_from<F>(data: Self::Data, mut ids: F) -> Result<Self, Self::Error>
+    where
+        F: FnMut(M) -> Option<Entity>,
+    {
+        let hits = data
+            .hits
+            .into_iter()
+            .map(|(amount, attacker)| Hit {
+                amount,
+                attacker: attacker.and_then(&mut ids),
+            })
+            .collect();
+        Ok(SufferDamage { hits })
+    }
+}
+
+// Left on whatever a hit brought down, for delete_the_dead to credit
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct SlainBy {
+    pub killer: Entity,
+}
+
+// Kills earn xp, and each level takes more of it than the last. What is
+// left over after a level up counts towards the next.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Experience {
+    pub level: i32,
+    pub xp: i32,
+}
+
+impl Experience {
+    pub fn xp_to_next(&self) -> i32 {
+        self.level * 100
+    }
+
+    pub fn can_level_up(&self) -> bool {
+        self.xp >= self.xp_to_next()
+    }
+}
+
+
+// A spawner builds up `progress` by `rate` every turn it gets and
+// spawns one copy of `spec` each time it crosses 1.0, as long as it
+// has fewer than `max_children` live children.
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Spawner {
-    rate: f32,
+    pub rate: f32,
+    pub progress: f32,
+    pub spec: MonsterSpec,
+    pub max_children: i32,
+    pub children: i32,
 }
 
 
@@ -63,17 +165,286 @@ pub struct Spawner {
 // And allow spawers to not spawn an unbounded number of thigns
 // The simplest thing we can do is let the child update the parent
 // to say i died. 
-#[derive(Component, Debug)]
+#[derive(Component, ConvertSaveload, Clone, Debug)]
 pub struct SpawnedBy {
-    parent: Entity,
+    pub parent: Entity,
+}
+
+// Anything that can be picked up and carried
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct Item {}
+
+// Drinking it restores this much hp, up to max_hp
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Potion {
+    pub heal_amount: i32,
+}
+
+// A carried item has no Position, only an owner
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct WantsToPickupItem {
+    pub item: Entity,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct WantsToDrinkPotion {
+    pub potion: Entity,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct WantsToDropItem {
+    pub item: Entity,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipmentSlot {
+    Melee,
+    Shield,
+    Head,
+    Body,
+    Ranged,
+}
+
+// Can be worn, one item per slot
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+}
+
+// Worn items are neither on the map nor in the backpack
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot,
+}
+
+// Added to the wearer's power and defense while equipped
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MeleePowerBonus {
+    pub power: i32,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DefenseBonus {
+    pub defense: i32,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct WantsToEquip {
+    pub item: Entity,
+}
+
+// Hits anything up to `range` tiles away in a clear line, for `damage`
+// less the target's defense. Worn in the ranged slot or thrown.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ranged {
+    pub range: i32,
+    pub damage: i32,
+}
+
+// Thrown from the backpack, it lands where it hits
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Throwable {}
+
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct WantsToShoot {
+    pub target: Entity,
+    pub with: Entity,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    // Hurts this much at the start of each of the bearer's turns
+    Poison { damage: i32 },
+    // Moves go in a random direction
+    Confusion,
+    // Turns are skipped
+    Sleep,
+    // Gains energy twice as fast
+    Haste,
+}
+
+impl Effect {
+    // Whether both are the same effect, however strong
+    pub fn same_kind(&self, other: &Effect) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    // How the log and HUD describe whoever has it
+    pub fn adjective(&self) -> &'static str {
+        match self {
+            Effect::Poison { .. } => "poisoned",
+            Effect::Confusion => "confused",
+            Effect::Sleep => "asleep",
+            Effect::Haste => "hasted",
+        }
+    }
+}
+
+// Wears off after `turns` of the bearer's own turns. Whoever brought it
+// on is credited with anything its poison kills.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    pub turns: i32,
+    pub from: Option<Entity>,
+}
+
+// An ActiveEffect as it is saved, the source swapped for its marker
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ActiveEffectData<M> {
+    pub effect: Effect,
+    pub turns: i32,
+    pub from: Option<M>,
+}
+
+fn effects_into<M, F>(effects: &[ActiveEffect], mut ids: F) -> Vec<ActiveEffectData<M>>
+where
+    F: FnMut(Entity) -> Option<M>,
+{
+    effects
+        .iter()
+        .map(|a| ActiveEffectData {
+            effect: a.effect,
+            turns: a.turns,
+            from: a.from.and_then(&mut ids),
+        })
+        .collect()
+}
+
+fn effects_from<M, F>(data: Vec<ActiveEffectData<M>>, mut ids: F) -> Vec<ActiveEffect>
+where
+    F: FnMut(M) -> Option<Entity>,
+{
+    data.into_iter()
+        .map(|a| ActiveEffect {
+            effect: a.effect,
+            turns: a.turns,
+            from: a.from.and_then(&mut ids),
+        })
+        .collect()
+}
+
+#[derive(Component, Clone, Debug, Default)]
+pub struct StatusEffects {
+    pub active: Vec<ActiveEffect>,
+}
+
+impl StatusEffects {
+    pub fn has(&self, effect: Effect) -> bool {
+        self.active.iter().any(|a| a.effect.same_kind(&effect))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StatusEffectsData<M> {
+    pub active: Vec<ActiveEffectData<M>>,
+}
+
+impl<M: Marker + Serialize> ConvertSaveload<M> for StatusEffects
+where
+    for<'de> M: Deserialize<'de>,
+{
+    type Data = StatusEffectsData<M>;
+    type Error = Infallible;
+
+    fn convert_into<F>(&self, ids: F) -> Result<Self::Data, Self::Error>
+    where
+        F: FnMut(Entity) -> Option<M>,
+    {
+        Ok(StatusEffectsData { active: effects_into(&self.active, ids) })
+    }
+
+    fn convert_from<F>(data: Self::Data, ids: F) -> Result<Self, Self::Error>
+    where
+        F: FnMut(M) -> Option<Entity>,
+    {
+        Ok(StatusEffects { active: effects_from(data.active, ids) })
+    }
+}
+
+// Effects waiting to be applied, gathered like SufferDamage so several
+// sources can hit the same target in one turn
+#[derive(Component, Clone, Debug)]
+pub struct GainsEffect {
+    pub pending: Vec<ActiveEffect>,
+}
+
+impl GainsEffect {
+    pub fn new_effect(store: &mut WriteStorage<GainsEffect>, target: Entity, effect: ActiveEffect) {
+        if let Some(gains) = store.get_mut(target) {
+            gains.pending.push(effect);
+        } else {
+            store
+                .insert(target, GainsEffect { pending: vec![effect] })
+                .expect("Unable to insert effect");
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GainsEffectData<M> {
+    pub pending: Vec<ActiveEffectData<M>>,
+}
+
+impl<M: Marker + Serialize> ConvertSaveload<M> for GainsEffect
+where
+    for<'de> M: Deserialize<'de>,
+{
+    type Data = GainsEffectData<M>;
+    type Error = Infallible;
+
+    fn convert_into<F>(&self, ids: F) -> Result<Self::Data, Self::Error>
+    where
+        F: FnMut(Entity) -> Option<M>,
+    {
+        Ok(GainsEffectData { pending: effects_into(&self.pending, ids) })
+    }
+
+    fn convert_from<F>(data: Self::Data, ids: F) -> Result<Self, Self::Error>
+    where
+        F: FnMut(M) -> Option<Entity>,
+    {
+        Ok(GainsEffect { pending: effects_from(data.pending, ids) })
+    }
+}
+
+// What a monster's hits, a thrown or shot item, or a potion does on top
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Inflicts {
+    pub effect: Effect,
+    pub turns: i32,
+}
+
+impl Inflicts {
+    // The effect as `from` passes it on
+    pub fn inflicted_by(&self, from: Option<Entity>) -> ActiveEffect {
+        ActiveEffect {
+            effect: self.effect,
+            turns: self.turns,
+            from,
+        }
+    }
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        attacker: Option<Entity>,
+    ) {
+        let hit = Hit { amount, attacker };
         if let Some(suffering) = store.get_mut(victim) {
-            suffering.amount.push(amount);
+            suffering.hits.push(hit);
         } else {
-            let dmg = SufferDamage { amount: vec![amount] };
+            let dmg = SufferDamage { hits: vec![hit] };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
@@ -90,4 +461,29 @@ pub fn register_all_components(ecs:&mut World) {
     ecs.register::<WantsToMelee>();
     ecs.register::<SufferDamage>();
     ecs.register::<BlocksTile>();
+    ecs.register::<Spawner>();
+    ecs.register::<SpawnedBy>();
+    ecs.register::<Energy>();
+    ecs.register::<MyTurn>();
+    ecs.register::<Item>();
+    ecs.register::<Potion>();
+    ecs.register::<InBackpack>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<WantsToDrinkPotion>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<Equippable>();
+    ecs.register::<Equipped>();
+    ecs.register::<MeleePowerBonus>();
+    ecs.register::<DefenseBonus>();
+    ecs.register::<WantsToEquip>();
+    ecs.register::<Ranged>();
+    ecs.register::<Throwable>();
+    ecs.register::<WantsToShoot>();
+    ecs.register::<StatusEffects>();
+    ecs.register::<GainsEffect>();
+    ecs.register::<Inflicts>();
+    ecs.register::<SlainBy>();
+    ecs.register::<Experience>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
-use rltk::{console, GameState};
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::console;
 use specs::prelude::*;
-use crate::{components::{CombatStats, SufferDamage, Player}, gamelog::GameLog, state::RunState};
+use crate::{components::{CombatStats, Equipped, Experience, Name, SlainBy, SpawnedBy, Spawner, SufferDamage, Player}, gamelog::GameLog, state::RunState};
 
 pub struct DamageSystem {}
 
@@ -8,19 +10,35 @@ impl<'a> System<'a> for DamageSystem {
     type SystemData = (
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, SlainBy>,
+        Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut inflict_damage) = data;
+        let (mut stats, mut inflict_damage, mut slain_by, entities) = data;
 
-        for (stats, damage) in (&mut stats, &inflict_damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
-            console::log(&format!("Damage: {}", damage.amount.iter().sum::<i32>()));
+        for (victim, stats, damage) in (&entities, &mut stats, &inflict_damage).join() {
+            for hit in damage.hits.iter() {
+                // Whoever lands the blow that brings it down gets the kill
+                let was_alive = stats.hp > 0;
+                stats.hp -= hit.amount;
+                if let (true, true, Some(killer)) = (was_alive, stats.hp <= 0, hit.attacker) {
+                    slain_by
+                        .insert(victim, SlainBy { killer })
+                        .expect("Unable to insert the killer");
+                }
+            }
+            console::log(format!("Damage: {}", damage.hits.iter().map(|h| h.amount).sum::<i32>()));
         }
         inflict_damage.clear();
     }
 }
 
+// What killing something with these stats is worth
+pub fn xp_for_kill(stats: &CombatStats) -> i32 {
+    stats.max_hp + 2 * (stats.power + stats.defense)
+}
+
 pub fn delete_the_dead(ecs: &mut World) {
     let mut dead : Vec<Entity> = Vec::new();
     {
@@ -42,7 +60,125 @@ pub fn delete_the_dead(ecs: &mut World) {
         }
     }
 
+    // Only the kills themselves count, not a brood dying off with them
+    award_experience(ecs, &dead);
+    bury_the_brood(ecs, &mut dead);
+
+    // Gear goes with whoever wore it
+    {
+        let entities = ecs.entities();
+        let equipped = ecs.read_storage::<Equipped>();
+        let worn: Vec<Entity> = (&entities, &equipped)
+            .join()
+            .filter(|(_, e)| dead.contains(&e.owner))
+            .map(|(item, _)| item)
+            .collect();
+        dead.extend(worn);
+    }
+
     for victim in dead {
         ecs.delete_entity(victim).expect("Unable to delete");
     }
-}
\ No newline at end of file
+}
+
+// Credits every kill to whoever landed the last blow, if they learn from
+// it. A player with enough xp for the next level gets to pick what grows.
+fn award_experience(ecs: &mut World, dead: &[Entity]) {
+    let player = *ecs.fetch::<Entity>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let slain_by = ecs.read_storage::<SlainBy>();
+    let mut experience = ecs.write_storage::<Experience>();
+    let mut runstate = ecs.fetch_mut::<RunState>();
+
+    for victim in dead.iter() {
+        let (Some(stats), Some(slain)) = (combat_stats.get(*victim), slain_by.get(*victim)) else {
+            continue;
+        };
+        if let Some(exp) = experience.get_mut(slain.killer) {
+            exp.xp += xp_for_kill(stats);
+        }
+    }
+    let ready = experience.get(player).is_some_and(|e| e.can_level_up());
+    if ready && *runstate != RunState::GameOver {
+        *runstate = RunState::LevelUp;
+    }
+}
+
+// When a spawner dies its whole brood dies with it. Any child that dies
+// on its own tells its parent, so the parent can spawn a replacement.
+fn bury_the_brood(ecs: &mut World, dead: &mut Vec<Entity>) {
+    let entities = ecs.entities();
+    let spawned_by = ecs.read_storage::<SpawnedBy>();
+    let names = ecs.read_storage::<Name>();
+    let mut spawners = ecs.write_storage::<Spawner>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+
+    let dead_parents: Vec<Entity> = dead
+        .iter()
+        .filter(|e| spawners.contains(**e))
+        .copied()
+        .collect();
+    for parent in dead_parents.iter() {
+        let brood = spawners.get(*parent).map_or(0, |s| s.children);
+        if brood == 0 {
+            continue;
+        }
+        if let Some(name) = names.get(*parent) {
+            gamelog.entries.push(format!("The brood of the {} withers away", name.name));
+        }
+    }
+    for (child, spawned) in (&entities, &spawned_by).join() {
+        if dead_parents.contains(&spawned.parent) && !dead.contains(&child) {
+            dead.push(child);
+        }
+    }
+
+    for victim in dead.iter() {
+        if let Some(spawned) = spawned_by.get(*victim) {
+            if let Some(parent) = spawners.get_mut(spawned.parent) {
+                parent.children -= 1;
+            }
+        }
+    }
+}
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_kills_are_credited_and_level_the_player(seed in any::<u64>(), victims in prop::collection::vec((1..40i32, 0..6i32, 0..4i32, any::<bool>()), 1..12)) {
+        use crate::state::test_state;
+        use rltk::VirtualKeyCode;
+
+        let mut gs = test_state(seed);
+        gs.run_until_input();
+        let player = *gs.ecs.fetch::<Entity>();
+        let base = gs.ecs.read_storage::<CombatStats>().get(player).unwrap().clone();
+
+        let mut earned = 0;
+        for (hp, power, defense, by_player) in victims {
+            let stats = CombatStats { max_hp: hp, hp, power, defense };
+            let victim = gs.ecs.create_entity().with(stats.clone()).build();
+            let attacker = if by_player { Some(player) } else { None };
+            // Only the blow that brings it down counts, not what follows
+            SufferDamage::new_damage(&mut gs.ecs.write_storage::<SufferDamage>(), victim, hp, attacker);
+            SufferDamage::new_damage(&mut gs.ecs.write_storage::<SufferDamage>(), victim, 5, Some(victim));
+            if by_player {
+                earned += xp_for_kill(&stats);
+            }
+        }
+        DamageSystem {}.run_now(&gs.ecs);
+        delete_the_dead(&mut gs.ecs);
+
+        let mut levels = 0;
+        while *gs.ecs.fetch::<RunState>() == RunState::LevelUp {
+            gs.step(Some(VirtualKeyCode::B));
+            levels += 1;
+        }
+        let exp = gs.ecs.read_storage::<Experience>().get(player).unwrap().clone();
+        prop_assert_eq!(exp.level, 1 + levels);
+        prop_assert!(!exp.can_level_up());
+        let spent: i32 = (1..exp.level).map(|level| Experience { level, xp: 0 }.xp_to_next()).sum();
+        prop_assert_eq!(exp.xp + spent, earned);
+        prop_assert_eq!(gs.ecs.read_storage::<CombatStats>().get(player).unwrap().power, base.power + levels);
+    }
+}
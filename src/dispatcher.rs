@@ -0,0 +1,254 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use specs::prelude::*;
+use std::fmt;
+
+use crate::{
+    damage_system::DamageSystem,
+    initiative_system::InitiativeSystem,
+    inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemEquipSystem, PotionUseSystem},
+    map_index_system::MapIndexingSystem, melee_combat_system::MeleeCombatSystem,
+    monster_ai_system::MonsterAI, ranged_combat_system::RangedCombatSystem,
+    spawner_system::SpawnerSystem,
+    status_system::{EffectSystem, StatusSystem},
+    visibility_system::VisibilitySystem,
+};
+
+type AddSystem = Box<dyn FnOnce(&mut DispatcherBuilder<'static, 'static>, &[&str])>;
+
+pub struct RegisteredSystem {
+    name: &'static str,
+    after: Vec<&'static str>,
+    before: Vec<&'static str>,
+    add: AddSystem,
+}
+
+impl RegisteredSystem {
+    // Runs once all of `names` have finished
+    pub fn after(&mut self, names: &[&'static str]) -> &mut Self {
+        self.after.extend_from_slice(names);
+        self
+    }
+
+    // Finishes before any of `names` starts
+    pub fn before(&mut self, names: &[&'static str]) -> &mut Self {
+        self.before.extend_from_slice(names);
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DispatchError {
+    DuplicateSystem(&'static str),
+    UnknownSystem {
+        name: &'static str,
+        wanted_by: &'static str,
+    },
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::DuplicateSystem(name) => {
+                write!(f, "a system called {} is already registered", name)
+            }
+            DispatchError::UnknownSystem { name, wanted_by } => {
+                write!(f, "{} is ordered against {}, which isn't registered", wanted_by, name)
+            }
+            DispatchError::Cycle(names) => {
+                write!(f, "these systems wait on each other: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+// Systems are registered by name and ordered against each other by name,
+// so a new one can be slotted in without touching the rest. Anything not
+// ordered against each other may run in parallel, as long as specs sees
+// no conflict between the storages they use.
+#[derive(Default)]
+pub struct SystemRegistry {
+    systems: Vec<RegisteredSystem>,
+}
+
+impl SystemRegistry {
+    pub fn new() -> SystemRegistry {
+        SystemRegistry::default()
+    }
+
+    pub fn add<S>(&mut self, system: S, name: &'static str) -> &mut RegisteredSystem
+    where
+        S: for<'c> System<'c> + Send + 'static,
+    {
+        self.systems.push(RegisteredSystem {
+            name,
+            after: Vec::new(),
+            before: Vec::new(),
+            add: Box::new(move |builder, deps| builder.add(system, name, deps)),
+        });
+        self.systems.last_mut().unwrap()
+    }
+
+    // The names in the order they'll be handed to specs, each after
+    // everything it depends on. Ties keep the order they were added in.
+    pub fn order(&self) -> Result<Vec<&'static str>, DispatchError> {
+        let deps = self.dependencies()?;
+        let mut done: Vec<&'static str> = Vec::new();
+        while done.len() < self.systems.len() {
+            let next = self
+                .systems
+                .iter()
+                .zip(deps.iter())
+                .find(|(s, d)| !done.contains(&s.name) && d.iter().all(|n| done.contains(n)));
+            match next {
+                Some((s, _)) => done.push(s.name),
+                None => {
+                    let stuck = self
+                        .systems
+                        .iter()
+                        .map(|s| s.name)
+                        .filter(|n| !done.contains(n))
+                        .collect();
+                    return Err(DispatchError::Cycle(stuck));
+                }
+            }
+        }
+        Ok(done)
+    }
+
+    pub fn build(self) -> Result<Dispatcher<'static, 'static>, DispatchError> {
+        let order = self.order()?;
+        let deps = self.dependencies()?;
+        let mut pending: Vec<Option<(RegisteredSystem, Vec<&'static str>)>> =
+            self.systems.into_iter().zip(deps).map(Some).collect();
+
+        let mut builder = DispatcherBuilder::new();
+        for name in order {
+            let (system, deps) = pending
+                .iter_mut()
+                .find(|p| p.as_ref().map(|(s, _)| s.name) == Some(name))
+                .and_then(Option::take)
+                .unwrap();
+            (system.add)(&mut builder, &deps);
+        }
+        Ok(builder.build())
+    }
+
+    // Everything each system has to wait for, with `before` turned around
+    fn dependencies(&self) -> Result<Vec<Vec<&'static str>>, DispatchError> {
+        let known = |name, wanted_by| {
+            if self.systems.iter().any(|s| s.name == name) {
+                Ok(())
+            } else {
+                Err(DispatchError::UnknownSystem { name, wanted_by })
+            }
+        };
+
+        let mut deps: Vec<Vec<&'static str>> = Vec::new();
+        for (i, system) in self.systems.iter().enumerate() {
+            if self.systems[..i].iter().any(|s| s.name == system.name) {
+                return Err(DispatchError::DuplicateSystem(system.name));
+            }
+            for name in system.after.iter().chain(system.before.iter()) {
+                known(name, system.name)?;
+            }
+            let mut wait_for = system.after.clone();
+            for other in self.systems.iter() {
+                if other.before.contains(&system.name) && !wait_for.contains(&other.name) {
+                    wait_for.push(other.name);
+                }
+            }
+            deps.push(wait_for);
+        }
+        Ok(deps)
+    }
+}
+
+// The game's systems. Replays depend on the order things happen in, so
+// anything touching the same data is ordered explicitly.
+pub fn default_system_registry() -> SystemRegistry {
+    let mut systems = SystemRegistry::new();
+    // Independent of each other, these two may run side by side
+    systems.add(VisibilitySystem {}, "visibility");
+    systems.add(InitiativeSystem {}, "initiative");
+    // Sleepers lose their turn before anyone acts on it
+    systems.add(StatusSystem {}, "status").after(&["initiative"]);
+    systems
+        .add(MonsterAI {}, "monster_ai")
+        .after(&["visibility", "status"]);
+    // Whatever the turn's actions asked of items happens before the map
+    // is indexed again
+    systems
+        .add(ItemCollectionSystem {}, "item_collection")
+        .after(&["monster_ai"]);
+    systems.add(ItemDropSystem {}, "item_drop").after(&["item_collection"]);
+    systems.add(PotionUseSystem {}, "potion_use").after(&["item_drop"]);
+    systems.add(ItemEquipSystem {}, "item_equip").after(&["potion_use"]);
+    systems.add(MapIndexingSystem {}, "map_indexing").after(&["item_equip"]);
+    systems.add(SpawnerSystem {}, "spawner").after(&["map_indexing"]);
+    systems.add(MeleeCombatSystem {}, "melee_combat").after(&["spawner"]);
+    systems
+        .add(RangedCombatSystem {}, "ranged_combat")
+        .after(&["melee_combat"]);
+    systems.add(EffectSystem {}, "effects").after(&["ranged_combat"]);
+    systems.add(DamageSystem {}, "damage").after(&["effects"]);
+    systems
+}
+
+#[cfg(test)]
+struct NoopSystem;
+
+#[cfg(test)]
+impl<'a> System<'a> for NoopSystem {
+    type SystemData = ();
+    fn run(&mut self, _: ()) {}
+}
+
+#[cfg(test)]
+const NAMES: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_order_respects_every_constraint(
+        edges in prop::collection::vec((0..6usize, 0..6usize, any::<bool>()), 0..12)
+    ) {
+        let mut systems = SystemRegistry::new();
+        for name in NAMES {
+            systems.add(NoopSystem, name);
+        }
+        // Only point edges from lower to higher names, so there is no cycle
+        for (a, b, before) in edges.iter().copied().filter(|(a, b, _)| a < b) {
+            if before {
+                systems.systems[a].before(&[NAMES[b]]);
+            } else {
+                systems.systems[b].after(&[NAMES[a]]);
+            }
+        }
+        let order = systems.order().unwrap();
+        let at = |n: &str| order.iter().position(|o| *o == n).unwrap();
+        for (a, b, _) in edges.iter().copied().filter(|(a, b, _)| a < b) {
+            prop_assert!(at(NAMES[a]) < at(NAMES[b]));
+        }
+        prop_assert!(systems.build().is_ok());
+    }
+
+    #[test]
+    fn test_cycles_and_unknown_names_are_refused(n in 2..6usize) {
+        let mut systems = SystemRegistry::new();
+        for i in 0..n {
+            systems.add(NoopSystem, NAMES[i]).after(&[NAMES[(i + 1) % n]]);
+        }
+        prop_assert!(matches!(systems.order(), Err(DispatchError::Cycle(_))));
+
+        let mut systems = SystemRegistry::new();
+        systems.add(NoopSystem, "a").before(&[NAMES[n]]);
+        prop_assert_eq!(
+            systems.order(),
+            Err(DispatchError::UnknownSystem { name: NAMES[n], wanted_by: "a" })
+        );
+    }
+}
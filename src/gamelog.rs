@@ -1,7 +1,9 @@
 
 
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GameLog {
     pub entries: Vec<String>,
 }
@@ -1,7 +1,20 @@
-use rltk::{Console, Point, Rltk, RGB};
+use rltk::{Algorithm2D, Point, Rltk, VirtualKeyCode, RGB};
 use specs::prelude::*;
 
-use crate::{components::{CombatStats, Player}, gamelog::GameLog};
+use crate::{
+    camera::viewport,
+    components::{
+        CombatStats, DefenseBonus, Equipped, Experience, MeleePowerBonus, Name, Player,
+        StatusEffects,
+    },
+    gamelog::GameLog,
+    inventory_system::backpack_of,
+    map::Map,
+    melee_combat_system::effective_stats,
+    player::LEVEL_UP_HP,
+    replay::ReplayPlayer,
+    targeting::{target_at, target_key, targets, valid_cells, visible_targets, Targeting},
+};
 
 pub struct UiConfig {
     pub fg: RGB,
@@ -11,18 +24,21 @@ pub struct UiConfig {
     pub ui_size: Point,
 }
 
-pub fn default_config() -> UiConfig {
+// The UI box takes the bottom 7 rows of whatever console we were given
+pub fn default_config(screen_width: u32, screen_height: u32) -> UiConfig {
+    let (w, h) = (screen_width as i32, screen_height as i32);
     UiConfig{
         fg: RGB::named(rltk::WHITE),
         bg: RGB::named(rltk::BLACK),
-        bounds: Point::new(79, 49),
-        ui_origin: Point::new(0, 43),
-        ui_size: Point::new(79, 6),
+        bounds: Point::new(w - 1, h - 1),
+        ui_origin: Point::new(0, h - 7),
+        ui_size: Point::new(w - 1, 6),
     }
 }
 
 pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
-    let default = default_config();
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let default = default_config(screen_width, screen_height);
     ctx.draw_box(
         default.ui_origin.x,
         default.ui_origin.y,
@@ -35,18 +51,50 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
     let combat_stats = ecs.read_storage::<CombatStats>();
     let player = ecs.read_storage::<Player>();
     let game_log = ecs.fetch::<GameLog>();
+    let map = ecs.fetch::<Map>();
 
     draw_player(player, combat_stats, ctx, &default);
+    draw_gear_stats(ecs, ctx, &default);
+    draw_experience(ecs, ctx, &default);
+    draw_status_effects(ecs, ctx, &default);
     draw_log(game_log, ctx, &default);
+    draw_depth(&map, ctx, &default);
+    if let Some(replay) = ecs.try_fetch::<ReplayPlayer>() {
+        draw_replay(&replay, ctx, &default);
+    }
 
-    let mouse_pos = ctx.mouse_pos();
+    // Only highlight the mouse when it is over a tile of the map
+    let mouse_pos = ctx.mouse_point();
+    let view = viewport(ecs, ctx);
+    let over = view.screen_to_world(mouse_pos);
+    if view.world_to_screen(over).is_some() && map.in_bounds(over) {
+        ctx.set_bg(mouse_pos.x, mouse_pos.y, RGB::named(rltk::MAGENTA));
+    }
+}
 
-    ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
+fn draw_depth(map: &Map, ctx: &mut Rltk, default: &UiConfig) {
+    ctx.print_color(
+        default.ui_origin.x + 2,
+        default.ui_origin.y,
+        RGB::named(rltk::CYAN),
+        default.bg,
+        format!("Depth: {}", map.depth),
+    );
+}
+
+fn draw_replay(replay: &ReplayPlayer, ctx: &mut Rltk, default: &UiConfig) {
+    let mode = if replay.running { "playing" } else { "space to step, enter to play" };
+    ctx.print_color(
+        default.ui_origin.x + 2,
+        default.ui_origin.y + default.ui_size.y,
+        RGB::named(rltk::MAGENTA),
+        default.bg,
+        format!("Replay {}/{} ({})", replay.next, replay.keys.len(), mode),
+    );
 }
 
 fn draw_log(game_log: specs::shred::Fetch<GameLog>, ctx: &mut Rltk, default: &UiConfig) {
-    let mut y = default.ui_origin.y + 2;
-    for s in game_log.entries.iter().rev() {
+    for (y, s) in (default.ui_origin.y + 2..).zip(game_log.entries.iter().rev()) {
         if y < default.ui_origin.y + default.ui_size.y {
             ctx.print_color(
                 default.ui_origin.x + 2,
@@ -56,7 +104,6 @@ fn draw_log(game_log: specs::shred::Fetch<GameLog>, ctx: &mut Rltk, default: &Ui
                 s.to_string(),
             );
         }
-        y += 1;
     }
 }
 
@@ -72,10 +119,213 @@ fn draw_player(player: Storage<Player, specs::shred::Fetch<specs::storage::Maske
 
         ctx.draw_bar_horizontal(default.ui_origin.x+28, 
             default.ui_origin.y, 
-            51,
+            default.ui_size.x - 28,
             stats.hp, 
             stats.max_hp, 
             RGB::named(rltk::RED), 
             RGB::named(rltk::BLACK))
     }
 }
+
+// Under the HP bar, each with the turns it has left
+fn draw_status_effects(ecs: &World, ctx: &mut Rltk, default: &UiConfig) {
+    let player = *ecs.fetch::<Entity>();
+    let statuses = ecs.read_storage::<StatusEffects>();
+    let Some(status) = statuses.get(player) else {
+        return;
+    };
+    let mut x = default.ui_origin.x + 28;
+    for active in status.active.iter() {
+        let text = format!("{} ({})", active.effect.adjective(), active.turns);
+        ctx.print_color(x, default.ui_origin.y + 1, RGB::named(rltk::ORANGE), default.bg, &text);
+        x += text.len() as i32 + 2;
+    }
+}
+
+// Under the depth, with how far the player is towards the next level
+fn draw_experience(ecs: &World, ctx: &mut Rltk, default: &UiConfig) {
+    let player = *ecs.fetch::<Entity>();
+    let experience = ecs.read_storage::<Experience>();
+    let Some(exp) = experience.get(player) else {
+        return;
+    };
+    ctx.print_color(
+        default.ui_origin.x + 2,
+        default.ui_origin.y + 1,
+        RGB::named(rltk::GOLD),
+        default.bg,
+        format!("Level {}  XP {}/{}", exp.level, exp.xp, exp.xp_to_next()),
+    );
+}
+
+// Power and defense with the player's gear counted in
+fn draw_gear_stats(ecs: &World, ctx: &mut Rltk, default: &UiConfig) {
+    let player = *ecs.fetch::<Entity>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let Some(stats) = combat_stats.get(player) else {
+        return;
+    };
+    let (power, defense) = effective_stats(
+        player,
+        stats,
+        &ecs.read_storage::<Equipped>(),
+        &ecs.read_storage::<MeleePowerBonus>(),
+        &ecs.read_storage::<DefenseBonus>(),
+    );
+    let text = format!("Power: {}  Defense: {}", power, defense);
+    ctx.print_color(
+        default.ui_origin.x + default.ui_size.x - 2 - text.len() as i32,
+        default.ui_origin.y + default.ui_size.y,
+        default.fg,
+        default.bg,
+        text,
+    );
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuResult {
+    NoSelection,
+    NewGame,
+    Continue,
+}
+
+pub fn main_menu(ctx: &mut Rltk) -> MainMenuResult {
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let default = default_config(screen_width, screen_height);
+    let y = default.bounds.y / 2;
+    ctx.print_color_centered(y - 2, RGB::named(rltk::YELLOW), default.bg, "Rouge");
+    ctx.print_color_centered(y, default.fg, default.bg, "(C) Continue the saved game");
+    ctx.print_color_centered(y + 1, default.fg, default.bg, "(N) Start a new game");
+
+    match ctx.key {
+        Some(VirtualKeyCode::C) => MainMenuResult::Continue,
+        Some(VirtualKeyCode::N) => MainMenuResult::NewGame,
+        _ => MainMenuResult::NoSelection,
+    }
+}
+
+// Lists what the player carries over the map, each under the letter that
+// picks it. Choosing is up to player::inventory_input, this only draws.
+pub fn show_inventory(ecs: &World, ctx: &mut Rltk, title: &str) {
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let default = default_config(screen_width, screen_height);
+    let player = *ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let carried = backpack_of(ecs, player);
+
+    let width = 31;
+    let height = carried.len() as i32 + 3;
+    let x = (default.bounds.x - width) / 2;
+    let y = (default.ui_origin.y - height) / 2;
+    ctx.draw_box(x, y, width, height, default.fg, default.bg);
+    ctx.print_color(x + 3, y, RGB::named(rltk::YELLOW), default.bg, title);
+    ctx.print_color(
+        x + 3,
+        y + height,
+        RGB::named(rltk::YELLOW),
+        default.bg,
+        "Any other key closes",
+    );
+
+    if carried.is_empty() {
+        ctx.print_color(x + 2, y + 2, default.fg, default.bg, "You carry nothing.");
+    }
+    for (row, (letter, item)) in (y + 2..).zip(('a'..='z').zip(carried)) {
+        ctx.set(x + 2, row, RGB::named(rltk::YELLOW), default.bg, rltk::to_cp437(letter));
+        let name = names.get(item).map_or("something", |n| n.name.as_str());
+        ctx.print_color(x + 4, row, default.fg, default.bg, name);
+    }
+}
+
+// What a level up can raise, under the letter that picks it. Choosing is
+// up to player::level_up_input.
+pub fn show_level_up(ecs: &World, ctx: &mut Rltk) {
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let default = default_config(screen_width, screen_height);
+    let player = *ecs.fetch::<Entity>();
+    let level = ecs.read_storage::<Experience>().get(player).map_or(1, |e| e.level);
+    let choices = [
+        format!("Toughness, {} more max hp", LEVEL_UP_HP),
+        "Strength, 1 more power".to_string(),
+        "Guard, 1 more defense".to_string(),
+    ];
+
+    let width = 31;
+    let height = choices.len() as i32 + 3;
+    let x = (default.bounds.x - width) / 2;
+    let y = (default.ui_origin.y - height) / 2;
+    ctx.draw_box(x, y, width, height, default.fg, default.bg);
+    ctx.print_color(
+        x + 3,
+        y,
+        RGB::named(rltk::YELLOW),
+        default.bg,
+        format!("Level {}! Raise what?", level + 1),
+    );
+    for (row, (letter, choice)) in (y + 2..).zip(('a'..='c').zip(choices.iter())) {
+        ctx.set(x + 2, row, RGB::named(rltk::YELLOW), default.bg, rltk::to_cp437(letter));
+        ctx.print_color(x + 4, row, default.fg, default.bg, choice);
+    }
+}
+
+// Shades every tile a shot could reach and lists who is in reach, the
+// one Tab has picked in yellow. A list too long for the screen scrolls to
+// keep that one in view. Only the first nine have a number to pick them
+// by, the rest are reached with Tab.
+pub fn show_targeting(ecs: &World, ctx: &mut Rltk) {
+    let (screen_width, screen_height) = ctx.get_char_size();
+    let default = default_config(screen_width, screen_height);
+    let (with, range, current) = {
+        let targeting = ecs.fetch::<Targeting>();
+        (targeting.with, targeting.range, targeting.current)
+    };
+    let view = viewport(ecs, ctx);
+    for cell in valid_cells(ecs, range) {
+        if let Some(screen) = view.world_to_screen(cell) {
+            ctx.set_bg(screen.x, screen.y, RGB::named(rltk::BLUE));
+        }
+    }
+
+    let names = ecs.read_storage::<Name>();
+    let found = targets(ecs, range);
+    let picked = current % found.len().max(1);
+    if let Some(screen) = found.get(picked).and_then(|(_, p)| view.world_to_screen(*p)) {
+        ctx.set_bg(screen.x, screen.y, RGB::named(rltk::YELLOW));
+    }
+
+    let title = format!(
+        "Aim the {}",
+        names.get(with).map_or("something", |n| n.name.as_str())
+    );
+    let width = 31;
+    let y = 1;
+    let shown = visible_targets(found.len(), picked, (default.ui_origin.y - y - 4).max(1) as usize);
+    let height = shown.len() as i32 + 3;
+    let x = default.bounds.x - width - 1;
+    ctx.draw_box(x, y, width, height, default.fg, default.bg);
+    ctx.print_color(x + 3, y, RGB::named(rltk::YELLOW), default.bg, title);
+    ctx.print_color(
+        x + 3,
+        y + height,
+        RGB::named(rltk::YELLOW),
+        default.bg,
+        "Tab next, F fire",
+    );
+    let listed = found.iter().enumerate().skip(shown.start).take(shown.len());
+    for (row, (n, (target, _))) in (y + 2..).zip(listed) {
+        let fg = if n == picked { RGB::named(rltk::YELLOW) } else { default.fg };
+        let name = names.get(*target).map_or("something", |n| n.name.as_str());
+        let label = target_key(n).map_or(" ".to_string(), |_| (n + 1).to_string());
+        ctx.print_color(x + 2, row, fg, default.bg, format!("{} {}", label, name));
+    }
+}
+
+// A click on a target while aiming counts as pressing its number
+pub fn clicked_target(ecs: &World, ctx: &Rltk) -> Option<VirtualKeyCode> {
+    if !ctx.left_click {
+        return None;
+    }
+    let range = ecs.try_fetch::<Targeting>()?.range;
+    let at = viewport(ecs, ctx).screen_to_world(ctx.mouse_point());
+    target_at(ecs, range, at).and_then(target_key)
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use crate::{
+    components::{Effect, Energy, MyTurn, StatusEffects},
+    state::RunState,
+};
+use specs::prelude::*;
+
+// What one turn costs. An entity with speed 100 gets one turn every tick
+// of the clock, 200 gets two and 50 gets one every other tick.
+pub const ACTION_COST: i32 = 100;
+
+pub struct InitiativeSystem {}
+
+impl<'a> System<'a> for InitiativeSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, RunState>,
+        WriteStorage<'a, Energy>,
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, StatusEffects>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, runstate, mut energies, mut my_turn, statuses) = data;
+
+        if *runstate != RunState::Ticking {
+            return;
+        }
+
+        // Last round of turns is over, whether or not anyone acted
+        my_turn.clear();
+
+        // Anyone with enough saved up gets a turn, it is paid for up front.
+        // Until someone can afford one, the clock moves on.
+        loop {
+            for (entity, energy) in (&entities, &mut energies).join() {
+                if energy.energy >= ACTION_COST {
+                    energy.energy -= ACTION_COST;
+                    my_turn
+                        .insert(entity, MyTurn {})
+                        .expect("Unable to insert turn");
+                }
+            }
+            if !my_turn.is_empty() {
+                return;
+            }
+            // Nobody would ever get a turn
+            if !(&energies).join().any(|e| e.speed > 0) {
+                return;
+            }
+            for (energy, status) in (&mut energies, statuses.maybe()).join() {
+                let hasted = status.is_some_and(|s| s.has(Effect::Haste));
+                energy.energy += if hasted { energy.speed * 2 } else { energy.speed };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_turns_follow_speed(speeds in prop::collection::vec(1..400i32, 1..10)) {
+        use crate::components::register_all_components;
+
+        let mut ecs = World::new();
+        register_all_components(&mut ecs);
+        ecs.insert(RunState::Ticking);
+        // The first entity moves at the player's pace and sets the clock
+        let all: Vec<Entity> = std::iter::once(100)
+            .chain(speeds.iter().copied())
+            .map(|speed| ecs.create_entity().with(Energy { speed, energy: 0 }).build())
+            .collect();
+
+        let mut turns = vec![0; all.len()];
+        while turns[0] < 20 {
+            InitiativeSystem {}.run_now(&ecs);
+            let my_turn = ecs.read_storage::<MyTurn>();
+            for (i, e) in all.iter().enumerate() {
+                if my_turn.contains(*e) {
+                    turns[i] += 1;
+                }
+            }
+        }
+        for (i, speed) in speeds.iter().enumerate() {
+            // Counting stops at the player's turn, which can come before
+            // the fast entities have used up that tick's energy
+            let expected = speed * 20 / ACTION_COST;
+            let unspent = speed / ACTION_COST + 1;
+            prop_assert!(
+                turns[i + 1] >= expected - unspent && turns[i + 1] <= expected + 1,
+                "speed {} had {} turns", speed, turns[i + 1]
+            );
+        }
+    }
+}
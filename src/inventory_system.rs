@@ -0,0 +1,365 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use specs::prelude::*;
+
+use crate::{
+    components::{
+        CombatStats, Equippable, Equipped, GainsEffect, InBackpack, Inflicts, Name, Position,
+        Potion, WantsToDrinkPotion, WantsToDropItem, WantsToEquip, WantsToPickupItem,
+    },
+    gamelog::GameLog,
+};
+
+// One letter each in the inventory overlay, a to z, which also fits the
+// overlay inside the map area
+pub const MAX_CARRIED: usize = 26;
+
+// "You" for the player, the entity's name for everyone else
+pub(crate) fn who(entity: Entity, player: Entity, names: &ReadStorage<Name>) -> String {
+    if entity == player {
+        return "You".to_string();
+    }
+    names
+        .get(entity)
+        .map_or_else(|| "Something".to_string(), |n| n.name.clone())
+}
+
+pub(crate) fn item_name(item: Entity, names: &ReadStorage<Name>) -> String {
+    names
+        .get(item)
+        .map_or_else(|| "something".to_string(), |n| n.name.clone())
+}
+
+// Conjugates `verb` for whoever `who` named
+pub(crate) fn verb(actor: &str, verb: &str) -> String {
+    if actor == "You" {
+        verb.to_string()
+    } else {
+        format!("{}s", verb)
+    }
+}
+
+pub struct ItemCollectionSystem {}
+
+impl<'a> System<'a> for ItemCollectionSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, WantsToPickupItem>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, InBackpack>,
+        Entities<'a>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player, mut gamelog, mut wants_pickup, mut positions, names, mut backpack, entities) =
+            data;
+
+        for (owner, pickup) in (&entities, &wants_pickup).join() {
+            if (&backpack).join().filter(|b| b.owner == owner).count() >= MAX_CARRIED {
+                if owner == *player {
+                    gamelog.entries.push("Your pack is full.".to_string());
+                }
+                continue;
+            }
+            positions.remove(pickup.item);
+            backpack
+                .insert(pickup.item, InBackpack { owner })
+                .expect("Unable to put the item in the backpack");
+            let actor = who(owner, *player, &names);
+            gamelog.entries.push(format!(
+                "{} {} up the {}.",
+                actor,
+                verb(&actor, "pick"),
+                item_name(pickup.item, &names)
+            ));
+        }
+        wants_pickup.clear();
+    }
+}
+
+pub struct ItemDropSystem {}
+
+impl<'a> System<'a> for ItemDropSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToDropItem>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InBackpack>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player, mut gamelog, entities, mut wants_drop, names, mut positions, mut backpack) =
+            data;
+
+        for (dropper, drop) in (&entities, &wants_drop).join() {
+            let Some(at) = positions.get(dropper).map(|p| p.point) else {
+                continue;
+            };
+            positions
+                .insert(drop.item, Position { point: at })
+                .expect("Unable to drop the item");
+            backpack.remove(drop.item);
+            let actor = who(dropper, *player, &names);
+            gamelog.entries.push(format!(
+                "{} {} the {}.",
+                actor,
+                verb(&actor, "drop"),
+                item_name(drop.item, &names)
+            ));
+        }
+        wants_drop.clear();
+    }
+}
+
+pub struct PotionUseSystem {}
+
+impl<'a> System<'a> for PotionUseSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToDrinkPotion>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Potion>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, Inflicts>,
+        WriteStorage<'a, GainsEffect>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player,
+            mut gamelog,
+            entities,
+            mut wants_drink,
+            names,
+            potions,
+            mut combat_stats,
+            inflicts,
+            mut gains_effect,
+        ) = data;
+
+        for (drinker, drink, stats) in (&entities, &wants_drink, &mut combat_stats).join() {
+            let Some(potion) = potions.get(drink.potion) else {
+                continue;
+            };
+            let healed = i32::min(stats.max_hp - stats.hp, potion.heal_amount).max(0);
+            stats.hp += healed;
+            let actor = who(drinker, *player, &names);
+            let mut line = format!(
+                "{} {} the {}",
+                actor,
+                verb(&actor, "drink"),
+                item_name(drink.potion, &names)
+            );
+            if potion.heal_amount > 0 {
+                line.push_str(&format!(", healing {} hp", healed));
+            }
+            line.push('.');
+            gamelog.entries.push(line);
+            if let Some(inflicts) = inflicts.get(drink.potion) {
+                // Nobody but the drinker is to blame
+                GainsEffect::new_effect(&mut gains_effect, drinker, inflicts.inflicted_by(None));
+            }
+            entities
+                .delete(drink.potion)
+                .expect("Unable to delete the potion");
+        }
+        wants_drink.clear();
+    }
+}
+
+// Whatever was worn in the slot goes back into the backpack
+pub struct ItemEquipSystem {}
+
+impl<'a> System<'a> for ItemEquipSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToEquip>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Equippable>,
+        WriteStorage<'a, Equipped>,
+        WriteStorage<'a, InBackpack>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (player, mut gamelog, entities, mut wants_equip, names, equippable, mut equipped, mut backpack) =
+            data;
+
+        for (owner, equip) in (&entities, &wants_equip).join() {
+            let Some(slot) = equippable.get(equip.item).map(|e| e.slot) else {
+                continue;
+            };
+            let actor = who(owner, *player, &names);
+            let worn: Vec<Entity> = (&entities, &equipped)
+                .join()
+                .filter(|(_, e)| e.owner == owner && e.slot == slot)
+                .map(|(item, _)| item)
+                .collect();
+            for item in worn {
+                equipped.remove(item);
+                backpack
+                    .insert(item, InBackpack { owner })
+                    .expect("Unable to put the item in the backpack");
+                gamelog.entries.push(format!(
+                    "{} {} off the {}.",
+                    actor,
+                    verb(&actor, "take"),
+                    item_name(item, &names)
+                ));
+            }
+            backpack.remove(equip.item);
+            equipped
+                .insert(equip.item, Equipped { owner, slot })
+                .expect("Unable to equip the item");
+            gamelog.entries.push(format!(
+                "{} {} the {}.",
+                actor,
+                verb(&actor, "equip"),
+                item_name(equip.item, &names)
+            ));
+        }
+        wants_equip.clear();
+    }
+}
+
+// What `owner` carries, in the order the inventory lists it
+pub fn backpack_of(ecs: &World, owner: Entity) -> Vec<Entity> {
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+    (&entities, &backpack)
+        .join()
+        .filter(|(_, b)| b.owner == owner)
+        .map(|(e, _)| e)
+        .collect()
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_potions_are_carried_then_drunk(seed in any::<u64>(), hp in 1..30i32, heal in 1..40i32) {
+        use crate::action::Action;
+        use crate::item::ItemSpec;
+        use crate::state::{test_state, RunState};
+        use rltk::VirtualKeyCode;
+
+        let mut gs = test_state(seed);
+        gs.run_until_input();
+        let player = *gs.ecs.fetch::<Entity>();
+        let at = *gs.ecs.fetch::<rltk::Point>();
+        let potion = ItemSpec {
+            glyph: rltk::to_cp437('!'),
+            name: "Test Potion".to_string(),
+            fg: rltk::RGB::named(rltk::MAGENTA),
+            bg: rltk::RGB::named(rltk::BLACK),
+            position: Some(Position { point: at }),
+            potion: Some(Potion { heal_amount: heal }),
+            equippable: None,
+            power_bonus: None,
+            defense_bonus: None,
+            ranged: None,
+            throwable: None,
+            inflicts: None,
+        }
+        .build_entity(&mut gs.ecs);
+
+        gs.play_action(Action::PickUp);
+        prop_assume!(gs.ecs.read_storage::<CombatStats>().get(player).is_some_and(|s| s.hp > 0));
+        prop_assert_eq!(backpack_of(&gs.ecs, player), vec![potion]);
+        prop_assert!(!gs.ecs.read_storage::<Position>().contains(potion));
+
+        let max_hp = {
+            let mut stats = gs.ecs.write_storage::<CombatStats>();
+            let stats = stats.get_mut(player).unwrap();
+            stats.hp = hp;
+            stats.max_hp
+        };
+        prop_assert_eq!(gs.play_turn(VirtualKeyCode::I), RunState::ShowInventory);
+        gs.play_turn(VirtualKeyCode::A);
+        prop_assert!(!gs.ecs.is_alive(potion));
+        prop_assert!(backpack_of(&gs.ecs, player).is_empty());
+        // Monsters may have hit back since, but never past what was healed
+        let now = gs.ecs.read_storage::<CombatStats>().get(player).map_or(0, |s| s.hp);
+        prop_assert!(now <= i32::min(max_hp, hp + heal));
+        let healed = i32::min(max_hp - hp, heal);
+        let expected = format!("You drink the Test Potion, healing {} hp.", healed);
+        prop_assert!(gs.ecs.fetch::<GameLog>().entries.contains(&expected));
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_the_pack_holds_one_item_per_letter(seed in any::<u64>(), carried in prop_oneof![0..MAX_CARRIED, MAX_CARRIED..MAX_CARRIED + 3]) {
+        use crate::action::Action;
+        use crate::state::test_state;
+
+        let mut gs = test_state(seed);
+        gs.run_until_input();
+        let player = *gs.ecs.fetch::<Entity>();
+        let at = *gs.ecs.fetch::<rltk::Point>();
+        let spec = crate::raws::test_raws().items[0].clone();
+        for _ in 0..carried {
+            spec.with_components(gs.ecs.create_entity()).with(InBackpack { owner: player }).build();
+        }
+        let item = spec.with_point(at).build_entity(&mut gs.ecs);
+
+        gs.play_action(Action::PickUp);
+        prop_assume!(gs.ecs.read_storage::<CombatStats>().get(player).is_some_and(|s| s.hp > 0));
+        let full = carried >= MAX_CARRIED;
+        let pack = backpack_of(&gs.ecs, player);
+        prop_assert_eq!(pack.len(), if full { carried } else { carried + 1 });
+        prop_assert_eq!(pack.contains(&item), !full);
+        prop_assert_eq!(gs.ecs.read_storage::<Position>().contains(item), full);
+        let told = gs.ecs.fetch::<GameLog>().entries.contains(&"Your pack is full.".to_string());
+        prop_assert_eq!(told, full);
+    }
+
+    #[test]
+    fn test_one_item_per_slot_counts(seed in any::<u64>(), picks in prop::collection::vec(any::<prop::sample::Index>(), 1..8)) {
+        use crate::action::{Action, ActionResolver};
+        use crate::components::{DefenseBonus, EquipmentSlot, MeleePowerBonus};
+        use crate::melee_combat_system::effective_stats;
+        use crate::state::test_state;
+
+        let raws = crate::raws::test_raws();
+        let gear: Vec<_> = raws.items.iter().filter(|i| i.equippable.is_some()).cloned().collect();
+        let mut gs = test_state(seed);
+        let player = *gs.ecs.fetch::<Entity>();
+        let base = gs.ecs.read_storage::<CombatStats>().get(player).unwrap().clone();
+
+        let mut wearing: Vec<Option<usize>> = vec![None; EquipmentSlot::Ranged as usize + 1];
+        for pick in picks {
+            let pick = pick.index(gear.len());
+            let spec = &gear[pick];
+            let item = spec.with_components(gs.ecs.create_entity()).with(InBackpack { owner: player }).build();
+            gs.ecs.system_data::<ActionResolver>().resolve(player, Action::Equip(item));
+            gs.dispatcher.dispatch(&gs.ecs);
+            gs.ecs.maintain();
+            wearing[spec.equippable.as_ref().unwrap().slot as usize] = Some(pick);
+        }
+
+        let equipped = gs.ecs.read_storage::<Equipped>();
+        let worn: Vec<_> = equipped.join().filter(|e| e.owner == player).map(|e| e.slot).collect();
+        prop_assert_eq!(worn.len(), wearing.iter().flatten().count());
+        let power: i32 = wearing.iter().flatten().filter_map(|i| gear[*i].power_bonus.as_ref()).map(|b| b.power).sum();
+        let defense: i32 = wearing.iter().flatten().filter_map(|i| gear[*i].defense_bonus.as_ref()).map(|b| b.defense).sum();
+        let stats = effective_stats(
+            player,
+            &base,
+            &equipped,
+            &gs.ecs.read_storage::<MeleePowerBonus>(),
+            &gs.ecs.read_storage::<DefenseBonus>(),
+        );
+        prop_assert_eq!(stats, (base.power + power, base.defense + defense));
+    }
+}
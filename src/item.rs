@@ -0,0 +1,57 @@
+use rltk::RGB;
+use rouge::FromSpec;
+use serde::{Deserialize, Serialize};
+use specs::{Builder, Entity};
+
+use crate::components::{
+    DefenseBonus, Equippable, Equipped, Inflicts, Item, MeleePowerBonus, Name, Position, Potion,
+    Ranged, Renderable, Throwable,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, FromSpec)]
+#[spec(with = Item {})]
+pub struct ItemSpec {
+    #[spec(into = Renderable)]
+    pub(crate) glyph: rltk::FontCharType,
+    #[spec(into = Name)]
+    pub(crate) name: String,
+    #[spec(into = Renderable)]
+    pub(crate) fg: RGB,
+    #[spec(into = Renderable)]
+    pub(crate) bg: RGB,
+    // None for items that start out carried or worn
+    #[spec(component)]
+    pub(crate) position: Option<Position>,
+    // What drinking it does, items without one can't be drunk
+    #[spec(component)]
+    pub(crate) potion: Option<Potion>,
+    #[spec(component)]
+    pub(crate) equippable: Option<Equippable>,
+    #[spec(component)]
+    pub(crate) power_bonus: Option<MeleePowerBonus>,
+    #[spec(component)]
+    pub(crate) defense_bonus: Option<DefenseBonus>,
+    #[spec(component)]
+    pub(crate) ranged: Option<Ranged>,
+    #[spec(component)]
+    pub(crate) throwable: Option<Throwable>,
+    // Given to whoever drinks it, or whoever it hits
+    #[spec(component)]
+    pub(crate) inflicts: Option<Inflicts>,
+}
+
+impl ItemSpec {
+    pub fn with_point(mut self, point: rltk::Point) -> ItemSpec {
+        self.position = Some(Position { point });
+        self
+    }
+
+    // The item already worn by `owner`, for gear monsters start with
+    pub fn equipped_by<B: Builder>(&self, builder: B, owner: Entity) -> B {
+        let equipped = self.equippable.as_ref().map(|e| Equipped {
+            owner,
+            slot: e.slot,
+        });
+        self.with_components(builder).maybe_with(equipped)
+    }
+}
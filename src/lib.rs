@@ -1,11 +1,22 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn;
+use syn::{parse_macro_input, DeriveInput};
 use crate::spec_macros::impl_spec_macro;
+use crate::tile_macros::impl_tile_macro;
 mod spec_macros;
+mod tile_macros;
 
-#[proc_macro_derive(FromSpec)]
+#[proc_macro_derive(FromSpec, attributes(spec))]
 pub fn from_spec_macro(input: TokenStream) -> TokenStream {
-    let ast = syn::parse(input).unwrap();
+    let ast = parse_macro_input!(input as DeriveInput);
     impl_spec_macro(&ast)
-}
\ No newline at end of file
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(TileMeta, attributes(tile))]
+pub fn tile_meta_macro(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    impl_tile_macro(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -1,61 +1,79 @@
-use crate::map::{default_map_config, make_dungeon, new_map};
-use crate::state::{RunState, State, create_player};
-use components::{register_all_components};
-use gamelog::GameLog;
-use monster::generate_monsters;
-use rltk::{RltkBuilder, RandomNumberGenerator};
-use specs::prelude::*;
+use crate::camera::default_camera_config;
+use crate::map::default_map_config;
+use crate::state::{RunState, State};
+use rltk::RltkBuilder;
+use crate::raws::{load_raws, RAWS_DIR};
+use crate::rng::seed_from_args;
+use crate::replay::{load_replay, replay_path_from_args, start_recording, ReplayPlayer};
+use crate::saveload::save_exists;
+pub mod action;
+pub mod camera;
 pub mod components;
 pub mod gamelog;
 pub mod map;
 pub mod player;
+pub mod raws;
 pub mod state;
 pub mod visibility_system;
 pub mod monster_ai_system;
 pub mod monster;
 pub mod map_index_system;
 pub mod damage_system;
+pub mod dispatcher;
 pub mod melee_combat_system;
+pub mod ranged_combat_system;
 pub mod gui;
-pub mod spawner;
+pub mod initiative_system;
+pub mod inventory_system;
+pub mod item;
+pub mod replay;
+pub mod rng;
+pub mod saveload;
+pub mod spawn_table;
+pub mod spawner_system;
+pub mod status_system;
+pub mod targeting;
+
+const MAP_WIDTH: usize = 200;
+const MAP_HEIGHT: usize = 200;
 
 fn main() -> rltk::BError {
+    // Pass --seed <n> to play a run again, the seed of every run is
+    // printed. --replay <file> also plays back the keys pressed in it.
+    let replay = match replay_path_from_args(std::env::args())? {
+        Some(path) => Some(load_replay(path)?),
+        None => None,
+    };
+    let seed = match &replay {
+        Some(replay) => replay.seed,
+        None => seed_from_args(std::env::args())?.unwrap_or_else(rand::random),
+    };
+    println!("Seed: {}", seed);
+    let raws = load_raws(RAWS_DIR)?;
+
     let mut context = RltkBuilder::simple80x50()
         .with_title("Rouge tutorial")
         .build()?;
     context.with_post_scanlines(true);
-    let mut gs = State { ecs: World::new()};
-    register_all_components(&mut gs.ecs);
-    gs.ecs.insert(RandomNumberGenerator::new());
-    gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(GameLog{
-        entries: vec!["Welcome to your nightmare".to_string()]
-    });
-    
-
-    // Get the screen size
-    let (screen_width, screen_height) = context.get_char_size();
-    let (sx, sy) = (
-        screen_width.try_into().unwrap(),
-        (screen_height-7).try_into().unwrap(),
-    );
-    let mut map = new_map(sx, sy);
+    // Lets the game see the window closing, so it can save first
+    rltk::INPUT.lock().activate_event_queue();
+    // The camera follows the player, so the map can be bigger than the screen
     let mut mgc = default_map_config();
-    mgc.max_room_x = screen_width.try_into().unwrap();
-    mgc.max_room_y = screen_height.try_into().unwrap();
-
-    // And add some padding
-    mgc.max_room_y = mgc.max_room_y-9;
-    mgc.max_room_x = mgc.max_room_x-2;
-    let mut rng = rltk::RandomNumberGenerator::new();
-    make_dungeon(&mgc, &mut rng, &mut map);
-    let start = map.rooms[0].center(); 
-
-    generate_monsters(&mut gs, &mut rng, &map);
-    create_player(&mut gs, start.x, start.y);
+    mgc.max_room_x = MAP_WIDTH as i32 - 2;
+    mgc.max_room_y = MAP_HEIGHT as i32 - 2;
+    mgc.num_rooms = 40;
+    let mut gs = State::new_run(seed, mgc, raws, MAP_WIDTH, MAP_HEIGHT)?;
+    gs.ecs.insert(default_camera_config());
 
-    gs.ecs.insert(start);
-    gs.ecs.insert(map);
+    // A new run is set up either way, continuing replaces it with the save
+    if save_exists() && replay.is_none() {
+        gs.ecs.insert(RunState::MainMenu);
+    }
+    match replay {
+        Some(replay) => gs.ecs.insert(ReplayPlayer::new(replay)),
+        None if *gs.ecs.fetch::<RunState>() == RunState::PreRun => start_recording(&mut gs.ecs),
+        None => {}
+    }
     gui::draw_ui(&gs.ecs, &mut context);
     rltk::main_loop(context, gs)
 }
@@ -3,8 +3,10 @@
 use proptest::{prelude::*, sample::subsequence};
 use rand::seq::{IteratorRandom, SliceRandom};
 use rltk::{
-    Algorithm2D, BaseMap, FontCharType, RandomNumberGenerator, Rect, Rltk, RGB, Point,
+    Algorithm2D, BaseMap, FontCharType, RandomNumberGenerator, Rect, RGB, Point,
 };
+use rouge::TileMeta;
+use serde::{Deserialize, Serialize};
 use specs::Entity;
 
 pub trait TileMeta {
@@ -18,41 +20,23 @@ pub trait TileMeta {
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize, TileMeta)]
 pub enum TileType {
+    #[tile(glyph = '#', color = "YELLOW", blocks)]
     Wall,
+    #[tile(glyph = '#', color = "GREEN", blocks)]
     CorWall,
+    #[tile(glyph = '.', color = "LIGHTBLUE")]
     Floor,
+    #[tile(glyph = ' ', color = "BLACK")]
     Empty,
+    #[tile(glyph = '>', color = "CYAN")]
+    DownStairs,
+    #[tile(glyph = '<', color = "CYAN")]
+    UpStairs,
 }
 
-impl TileMeta for TileType {
-    fn is_blocker(&self) -> bool {
-        match self {
-            TileType::Wall => true,
-            TileType::CorWall => true,
-            _ => false,
-        }
-    }
-    fn glpyh(&self) -> FontCharType {
-        match self {
-            TileType::Wall => rltk::to_cp437('#'),
-            TileType::CorWall => rltk::to_cp437('#'),
-            TileType::Floor => rltk::to_cp437('.'),
-            TileType::Empty => rltk::to_cp437(' '),
-        }
-    }
-    fn color(&self) -> RGB {
-        match self {
-            TileType::Wall => RGB::named(rltk::YELLOW),
-            TileType::CorWall => RGB::named(rltk::GREEN),
-            TileType::Floor => RGB::named(rltk::LIGHTBLUE),
-            TileType::Empty => RGB::from_f32(0.0, 0.0, 0.0),
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Map {
     pub tiles: Vec<TileType>,
     pub width: i32,
@@ -60,12 +44,29 @@ pub struct Map {
     pub rooms: Vec<Rect>,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
+    // Both rebuilt by the MapIndexingSystem, so they are not saved
+    #[serde(skip)]
     pub tile_content: Vec<Vec<Entity>>,
+    #[serde(skip)]
     pub blocked: Vec<bool>,
     pub depth: i32,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+// Which algorithm make_dungeon uses to lay out the rooms
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MapGenerator {
+    // Rejection sample num_rooms random rectangles, then join some of them
+    RoomsAndCorridors,
+    // Recursively split the map and put one room in each leaf. This
+    // ignores num_rooms, the split sizes decide how many rooms there are.
+    Bsp,
+    // Random noise smoothed into caves. There are no rooms, so everything
+    // that needs a place to stand goes through find_starting_position
+    // and spawn_regions instead of Map.rooms.
+    Cave { fill_percent: i32, iterations: i32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MapGenConfig {
     pub max_room_width: i32,
     pub max_room_height: i32,
@@ -78,6 +79,125 @@ pub struct MapGenConfig {
     pub num_rooms: usize,
     pub corridor_size: i32,
     pub room_max_connections: usize,
+    pub generator: MapGenerator,
+    // How many random rectangles generate_some_rooms may try before it
+    // gives up on placing num_rooms rooms
+    pub max_room_attempts: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapGenError {
+    InvalidRoomWidth { min: i32, max: i32 },
+    InvalidRoomHeight { min: i32, max: i32 },
+    NegativeOrigin { x: i32, y: i32 },
+    // The area between min_room_* and max_room_* can't hold the widest
+    // or tallest room the config allows
+    AreaTooNarrow { available: i32, needed: i32 },
+    AreaTooShort { available: i32, needed: i32 },
+    NoRooms,
+    InvalidCorridorSize(i32),
+    // connect_some_rooms picks from 1..room_max_connections, so it needs at least 2
+    TooFewConnections(usize),
+    InvalidFillPercent(i32),
+    NegativeIterations(i32),
+    LargerThanMap { config: Point, map: Point },
+    OutOfAttempts { placed: usize, wanted: usize },
+    Disconnected { unreachable: usize },
+    NotEnoughFloor { floor: usize },
+}
+
+impl std::fmt::Display for MapGenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapGenError::InvalidRoomWidth { min, max } => {
+                write!(f, "room width range {}..{} is empty or below 1", min, max)
+            }
+            MapGenError::InvalidRoomHeight { min, max } => {
+                write!(f, "room height range {}..{} is empty or below 1", min, max)
+            }
+            MapGenError::NegativeOrigin { x, y } => {
+                write!(f, "rooms can't start at negative coordinates ({}, {})", x, y)
+            }
+            MapGenError::AreaTooNarrow { available, needed } => {
+                write!(f, "room area is {} wide but rooms can be {} wide", available, needed)
+            }
+            MapGenError::AreaTooShort { available, needed } => {
+                write!(f, "room area is {} tall but rooms can be {} tall", available, needed)
+            }
+            MapGenError::NoRooms => write!(f, "the dungeon needs at least one room"),
+            MapGenError::InvalidCorridorSize(size) => write!(f, "corridor size {} is below 1", size),
+            MapGenError::TooFewConnections(n) => {
+                write!(f, "room_max_connections is {} but must be at least 2", n)
+            }
+            MapGenError::InvalidFillPercent(p) => write!(f, "cave fill percent {} is outside 0..=100", p),
+            MapGenError::NegativeIterations(n) => write!(f, "cave iterations {} is negative", n),
+            MapGenError::LargerThanMap { config, map } => write!(
+                f,
+                "rooms reach ({}, {}) but the map is only {}x{}",
+                config.x, config.y, map.x, map.y
+            ),
+            MapGenError::OutOfAttempts { placed, wanted } => {
+                write!(f, "only placed {} of {} rooms before running out of attempts", placed, wanted)
+            }
+            MapGenError::Disconnected { unreachable } => {
+                write!(f, "{} rooms can't be reached from the start", unreachable)
+            }
+            MapGenError::NotEnoughFloor { floor } => {
+                write!(f, "only {} walkable tiles were generated", floor)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapGenError {}
+
+impl MapGenConfig {
+    // Checks everything the generators would otherwise panic or loop
+    // forever on. It doesn't know the map size, make_dungeon checks that.
+    pub fn validate(&self) -> Result<(), MapGenError> {
+        if let MapGenerator::Cave { fill_percent, iterations } = self.generator {
+            if !(0..=100).contains(&fill_percent) {
+                return Err(MapGenError::InvalidFillPercent(fill_percent));
+            }
+            if iterations < 0 {
+                return Err(MapGenError::NegativeIterations(iterations));
+            }
+            return Ok(());
+        }
+
+        // build_room_rect draws sizes from min..max, so max is exclusive
+        if self.min_room_width < 1 || self.max_room_width <= self.min_room_width {
+            return Err(MapGenError::InvalidRoomWidth { min: self.min_room_width, max: self.max_room_width });
+        }
+        if self.min_room_height < 1 || self.max_room_height <= self.min_room_height {
+            return Err(MapGenError::InvalidRoomHeight { min: self.min_room_height, max: self.max_room_height });
+        }
+        if self.min_room_x < 0 || self.min_room_y < 0 {
+            return Err(MapGenError::NegativeOrigin { x: self.min_room_x, y: self.min_room_y });
+        }
+        if self.max_room_x - self.min_room_x < self.max_room_width {
+            return Err(MapGenError::AreaTooNarrow {
+                available: self.max_room_x - self.min_room_x,
+                needed: self.max_room_width,
+            });
+        }
+        if self.max_room_y - self.min_room_y < self.max_room_height {
+            return Err(MapGenError::AreaTooShort {
+                available: self.max_room_y - self.min_room_y,
+                needed: self.max_room_height,
+            });
+        }
+        if self.num_rooms == 0 {
+            return Err(MapGenError::NoRooms);
+        }
+        if self.corridor_size < 1 {
+            return Err(MapGenError::InvalidCorridorSize(self.corridor_size));
+        }
+        if self.room_max_connections < 2 {
+            return Err(MapGenError::TooFewConnections(self.room_max_connections));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -96,7 +216,9 @@ prop_compose! {
                           num_rooms in Just(10),
                           min_room_y in Just(min_room_y),
                           corridor_size in Just(3),
-                          room_max_connections in Just(3)
+                          room_max_connections in Just(3),
+                          generator in prop_oneof![Just(MapGenerator::RoomsAndCorridors), Just(MapGenerator::Bsp)],
+                          max_room_attempts in Just(10_000)
                         ) -> MapGenConfig {
         MapGenConfig{
             max_room_width,
@@ -109,7 +231,9 @@ prop_compose! {
             max_room_y,
             num_rooms,
             corridor_size,
-            room_max_connections
+            room_max_connections,
+            generator,
+            max_room_attempts
         }
     }
 }
@@ -127,13 +251,11 @@ pub fn default_map_config() -> MapGenConfig {
         num_rooms: 10,
         corridor_size: 3,
         room_max_connections: 2,
+        generator: MapGenerator::RoomsAndCorridors,
+        max_room_attempts: 10_000,
     }
 }
 
-pub fn xy_idx(x: i32, y: i32) -> usize {
-    (y as usize * 80) + x as usize
-}
-
 pub fn new_map(width: usize, height: usize) -> Map {
     Map {
         tiles: vec![TileType::Empty; width * height],
@@ -148,39 +270,12 @@ pub fn new_map(width: usize, height: usize) -> Map {
     }
 }
 
-pub fn draw_map(map: &Map, ctx: &mut Rltk) {
-    let (screen_width, _screen_height) = ctx.get_char_size();
-    let mut y = 0;
-    let mut x = 0;
-
-    for (idx, tile) in map.tiles.iter().enumerate() {
-        // Render a tile depending upon the tile type
-        if map.revealed_tiles[idx] {
-            let glyph = tile.glpyh();
-            let fg = if !map.visible_tiles[idx] {
-                tile.color().to_greyscale()
-            } else {
-                tile.color()
-            };
-            let bg = RGB::from_f32(0.0, 0.0, 0.0);
-            ctx.set(x, y, fg, bg, glyph);
-        }
-        x += 1; // Move the cursor right
-        if x > screen_width - 1 {
-            // If it reaches the end of the line
-            x = 0; // Move it back to the start
-            y += 1; // Move it down one line
-        }
-    }
-}
-
 pub fn build_room_rect(rng: &mut RandomNumberGenerator, mgc: &MapGenConfig) -> Rect {
     let w = rng.range(mgc.min_room_width, mgc.max_room_width);
     let h = rng.range(mgc.min_room_height, mgc.max_room_height);
     let x = rng.range(mgc.min_room_x, mgc.max_room_x - w);
     let y = rng.range(mgc.min_room_y, mgc.max_room_y - h);
-    let new_room = Rect::with_size(x, y, w, h);
-    new_room
+    Rect::with_size(x, y, w, h)
 }
 
 #[cfg(test)]
@@ -205,7 +300,7 @@ pub fn room_fits_in_map(room: &Rect, mgc: &MapGenConfig) -> bool {
     room.x1 > 0 && room.x2 < mgc.max_room_x && room.y1 > 0 && room.y2 < mgc.max_room_y
 }
 
-pub fn room_does_not_overlap(room: &Rect, rooms: &Vec<Rect>) -> bool {
+pub fn room_does_not_overlap(room: &Rect, rooms: &[Rect]) -> bool {
     for other_room in rooms.iter() {
         if room == other_room {
             continue;
@@ -217,19 +312,28 @@ pub fn room_does_not_overlap(room: &Rect, rooms: &Vec<Rect>) -> bool {
     true
 }
 
-pub fn room_works(room: &Rect, mgc: &MapGenConfig, rooms: &Vec<Rect>) -> bool {
+pub fn room_works(room: &Rect, mgc: &MapGenConfig, rooms: &[Rect]) -> bool {
     room_fits_in_map(room, mgc) && room_does_not_overlap(room, rooms)
 }
 
-pub fn generate_some_rooms(rng: &mut RandomNumberGenerator, mgc: &MapGenConfig) -> Vec<Rect> {
+pub fn generate_some_rooms(
+    rng: &mut RandomNumberGenerator,
+    mgc: &MapGenConfig,
+) -> Result<Vec<Rect>, MapGenError> {
     let mut rooms: Vec<Rect> = vec![];
-    while rooms.len() < mgc.num_rooms.try_into().unwrap() {
+    for _ in 0..mgc.max_room_attempts {
+        if rooms.len() >= mgc.num_rooms {
+            break;
+        }
         let new_room = build_room_rect(rng, mgc);
         if room_works(&new_room, mgc, &rooms) {
             rooms.push(new_room);
         }
     }
-    rooms
+    if rooms.len() < mgc.num_rooms {
+        return Err(MapGenError::OutOfAttempts { placed: rooms.len(), wanted: mgc.num_rooms });
+    }
+    Ok(rooms)
 }
 
 
@@ -245,9 +349,12 @@ fn arb_rooms(
     mgc: &MapGenConfig,
     rng: &mut rltk::RandomNumberGenerator,
 ) -> impl Strategy<Value = RoomCase> {
-    let rooms = subsequence(generate_some_rooms(rng, mgc), mgc.num_rooms);
+    let rooms = subsequence(
+        generate_some_rooms(rng, mgc).expect("The default config should always fit"),
+        mgc.num_rooms,
+    );
 
-    let new_mgc = mgc.clone();
+    let new_mgc = *mgc;
     rooms.prop_flat_map(move |rooms| {
         Just(RoomCase {
             rooms,
@@ -261,7 +368,7 @@ fn arb_rooms(
 proptest! {
     #[test]
     fn test_generate_some_rooms(room_case in arb_rooms(&default_map_config(), &mut rltk::RandomNumberGenerator::seeded(0))) {
-        prop_assert!(room_case.rooms.len() == room_case.mgc.num_rooms.try_into().unwrap());
+        prop_assert!(room_case.rooms.len() == room_case.mgc.num_rooms);
         for room in room_case.rooms.iter() {
             prop_assert!(room_fits_in_map(room, &room_case.mgc));
             prop_assert!(room_does_not_overlap(room, &room_case.rooms));
@@ -392,18 +499,17 @@ pub fn connect_some_rooms(
     mgc: &MapGenConfig,
     rng: &mut RandomNumberGenerator,
     origin: &Rect,
-    rooms: &Vec<Rect>,
+    rooms: &[Rect],
 ) -> Vec<Rect> {
     let connects = rng.range(1, mgc.room_max_connections);
-    let mut shuffled = rooms.clone();
+    let mut shuffled = rooms.to_vec();
     shuffled.shuffle(rng.get_rng());
     shuffled
         .iter()
         .filter(|r| *r != origin)
         .take(connects)
-        .map(|target| 
-            Vec::from(connect_two_rooms(mgc, rng, origin, target)))
-        .flatten()
+        .flat_map(|target| 
+            connect_two_rooms(mgc, rng, origin, target))
         .filter(|r| 
             room_fits_in_map(r, mgc))
         .collect()
@@ -412,28 +518,128 @@ pub fn connect_some_rooms(
 pub fn generate_rooms_and_corridors(
     mgc: &MapGenConfig,
     rng: &mut RandomNumberGenerator,
-) -> (Vec<Rect>, Vec<Rect>) {
-    let rooms = generate_some_rooms(rng, mgc);
+) -> Result<(Vec<Rect>, Vec<Rect>), MapGenError> {
+    let rooms = generate_some_rooms(rng, mgc)?;
     // generate the corridors,sometimes connecting rooms
     let corridors = rooms
         .iter()
-        .map(|room| 
+        .flat_map(|room| 
             connect_some_rooms(mgc, rng, room, &rooms))
-        .flatten()
         .collect();
-    (rooms, corridors)
+    Ok((rooms, corridors))
+}
+
+pub fn generate_bsp_rooms_and_corridors(
+    mgc: &MapGenConfig,
+    rng: &mut RandomNumberGenerator,
+) -> Result<(Vec<Rect>, Vec<Rect>), MapGenError> {
+    let mut rooms = vec![];
+    let mut corridors = vec![];
+    let area = Rect::with_exact(mgc.min_room_x, mgc.min_room_y, mgc.max_room_x, mgc.max_room_y);
+    bsp_split(mgc, rng, area, &mut rooms, &mut corridors);
+    if rooms.is_empty() {
+        return Err(MapGenError::NoRooms);
+    }
+    Ok((rooms, corridors))
+}
+
+// Splits area in two along its longer side until the pieces are small
+// enough to hold a single room. Returns one room from the subtree so the
+// caller can join it to the room from the sibling subtree.
+fn bsp_split(
+    mgc: &MapGenConfig,
+    rng: &mut RandomNumberGenerator,
+    area: Rect,
+    rooms: &mut Vec<Rect>,
+    corridors: &mut Vec<Rect>,
+) -> Option<Rect> {
+    // Every leaf keeps a one tile border between its room and its edges
+    let min_leaf_w = mgc.min_room_width + 2;
+    let min_leaf_h = mgc.min_room_height + 2;
+    if area.width() < min_leaf_w || area.height() < min_leaf_h {
+        return None;
+    }
+
+    let can_split_x = area.width() >= min_leaf_w * 2;
+    let can_split_y = area.height() >= min_leaf_h * 2;
+    let too_wide = area.width() > mgc.max_room_width + 2;
+    let too_tall = area.height() > mgc.max_room_height + 2;
+
+    let split_x = match (can_split_x && too_wide, can_split_y && too_tall) {
+        (false, false) => {
+            let room = bsp_leaf_room(mgc, rng, &area);
+            rooms.push(room);
+            return Some(room);
+        }
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => area.width() >= area.height(),
+    };
+
+    let (first, second) = if split_x {
+        let at = rng.range(area.x1 + min_leaf_w, area.x2 - min_leaf_w + 1);
+        (
+            Rect::with_exact(area.x1, area.y1, at, area.y2),
+            Rect::with_exact(at, area.y1, area.x2, area.y2),
+        )
+    } else {
+        let at = rng.range(area.y1 + min_leaf_h, area.y2 - min_leaf_h + 1);
+        (
+            Rect::with_exact(area.x1, area.y1, area.x2, at),
+            Rect::with_exact(area.x1, at, area.x2, area.y2),
+        )
+    };
+
+    let left = bsp_split(mgc, rng, first, rooms, corridors);
+    let right = bsp_split(mgc, rng, second, rooms, corridors);
+    match (left, right) {
+        (Some(l), Some(r)) => {
+            corridors.extend(connect_two_rooms(mgc, rng, &l, &r));
+            Some(if rng.roll_dice(1, 2) == 1 { l } else { r })
+        }
+        (l, r) => l.or(r),
+    }
+}
+
+fn bsp_leaf_room(mgc: &MapGenConfig, rng: &mut RandomNumberGenerator, leaf: &Rect) -> Rect {
+    let max_w = i32::min(mgc.max_room_width, leaf.width() - 2);
+    let max_h = i32::min(mgc.max_room_height, leaf.height() - 2);
+    let w = rng.range(mgc.min_room_width, max_w + 1);
+    let h = rng.range(mgc.min_room_height, max_h + 1);
+    let x = rng.range(leaf.x1 + 1, leaf.x2 - w);
+    let y = rng.range(leaf.y1 + 1, leaf.y2 - h);
+    Rect::with_size(x, y, w, h)
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_bsp_rooms_fit_and_do_not_overlap(mgc in arb_map_gen_config(), seed in any::<u64>()) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let (rooms, _) = generate_bsp_rooms_and_corridors(&mgc, &mut rng).unwrap();
+        prop_assert!(!rooms.is_empty());
+        for room in rooms.iter() {
+            prop_assert!(room_fits_in_map(room, &mgc));
+            prop_assert!(room_does_not_overlap(room, &rooms));
+            prop_assert!(room.width() >= mgc.min_room_width);
+            prop_assert!(room.width() <= mgc.max_room_width);
+            prop_assert!(room.height() >= mgc.min_room_height);
+            prop_assert!(room.height() <= mgc.max_room_height);
+        }
+    }
 }
 
 fn add_rect_to_map(rect: &Rect, map: &mut Map, floor_type: TileType, wall_type: TileType) {
     rect.point_set().iter().for_each(|p| {
+        let idx = map.xy_idx(p.x, p.y);
         // Build the floors where the point is not on and edge
         // and walls where it is by using a match
         match (p.x, p.y) {
             (x, y) if x == rect.x1 || x == rect.x2 - 1 || y == rect.y1 || y == rect.y2 - 1 => {
-                map.tiles[xy_idx(p.x, p.y)] = wall_type;
+                map.tiles[idx] = wall_type;
             }
             _ => {
-                map.tiles[xy_idx(p.x, p.y)] = floor_type;
+                map.tiles[idx] = floor_type;
             }
         }
     });
@@ -457,11 +663,11 @@ pub fn add_doors(map: &mut Map) {
             // If the tile to the left and right are floors
             // Self, Left, Right, Up, Down
             match (
-                map.tiles[xy_idx(x, y)],
-                map.tiles[xy_idx(x - 1, y)],
-                map.tiles[xy_idx(x + 1, y)],
-                map.tiles[xy_idx(x, y - 1)],
-                map.tiles[xy_idx(x, y + 1)],
+                map.tiles[map.xy_idx(x, y)],
+                map.tiles[map.xy_idx(x - 1, y)],
+                map.tiles[map.xy_idx(x + 1, y)],
+                map.tiles[map.xy_idx(x, y - 1)],
+                map.tiles[map.xy_idx(x, y + 1)],
             ) {
                 (
                     TileType::Wall,
@@ -471,7 +677,8 @@ pub fn add_doors(map: &mut Map) {
                     TileType::Wall,
                 ) => {
                     // Make it a door
-                    map.tiles[xy_idx(x, y)] = TileType::Floor;
+                    let idx = map.xy_idx(x, y);
+                    map.tiles[idx] = TileType::Floor;
                 }
                 (
                     TileType::Wall,
@@ -481,7 +688,8 @@ pub fn add_doors(map: &mut Map) {
                     TileType::Floor,
                 ) => {
                     // Make it a door
-                    map.tiles[xy_idx(x, y)] = TileType::Floor;
+                    let idx = map.xy_idx(x, y);
+                    map.tiles[idx] = TileType::Floor;
                 }
                 _ => {}
             }
@@ -489,24 +697,291 @@ pub fn add_doors(map: &mut Map) {
     }
 }
 
-pub fn find_starting_position(map: &mut Map) -> Point {
-    let mut starting_position = Point::zero();
-    for (i, tile) in map.tiles.iter().enumerate() {
-        if *tile == TileType::Floor {
-            // This is werid becuase of how the array is packed
-            starting_position = Point::new(i as i32 % map.width, i as i32 / map.width);
-            break;
+// The centre of the first room, or for maps without rooms the floor
+// tile closest to the middle of the map. Once stairs are placed that
+// tile is the way up, so it still counts.
+pub fn find_starting_position(map: &Map) -> Point {
+    if let Some(room) = map.rooms.first() {
+        return room.center();
+    }
+    let middle = Point::new(map.width / 2, map.height / 2);
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| matches!(tile, TileType::Floor | TileType::UpStairs))
+        .map(|(idx, _)| map.index_to_point2d(idx))
+        .min_by_key(|p| (p.x - middle.x).abs() + (p.y - middle.y).abs())
+        .unwrap_or_else(Point::zero)
+}
+
+// Maps without rooms are cut into squares this wide for spawning
+const SPAWN_CHUNK: i32 = 12;
+
+fn spawn_chunk(map: &Map, p: Point) -> i32 {
+    let chunks_x = (map.width + SPAWN_CHUNK - 1) / SPAWN_CHUNK;
+    (p.y / SPAWN_CHUNK) * chunks_x + p.x / SPAWN_CHUNK
+}
+
+// Whether `p` is in the first room, or on maps without rooms in the chunk
+// of the starting position. That is where the player arrives, so nothing
+// is spawned there.
+pub fn in_arrival_area(map: &Map, p: Point) -> bool {
+    match map.rooms.first() {
+        Some(room) => room.point_in_rect(p),
+        None => spawn_chunk(map, p) == spawn_chunk(map, find_starting_position(map)),
+    }
+}
+
+// The floor tiles monsters can be placed on, split into areas that each
+// get their own monsters. Rooms are their own areas, maps without rooms
+// are cut into square chunks. The arrival area is left out, going by
+// position rather than tiles since stairs may have been put in it.
+pub fn spawn_regions(map: &Map) -> Vec<Vec<Point>> {
+    if !map.rooms.is_empty() {
+        return map
+            .rooms
+            .iter()
+            .skip(1)
+            .map(|room| {
+                // Walk the room in order rather than through point_set, which
+                // is a HashSet and would make the spawn order differ per run
+                let mut floor = vec![];
+                room.for_each(|p| {
+                    if map.tiles[map.xy_idx(p.x, p.y)] == TileType::Floor {
+                        floor.push(p);
+                    }
+                });
+                floor
+            })
+            .collect();
+    }
+
+    let chunks_x = (map.width + SPAWN_CHUNK - 1) / SPAWN_CHUNK;
+    let chunks_y = (map.height + SPAWN_CHUNK - 1) / SPAWN_CHUNK;
+    let arrival = spawn_chunk(map, find_starting_position(map));
+    let mut regions = vec![Vec::new(); (chunks_x * chunks_y) as usize];
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        if *tile != TileType::Floor {
+            continue;
+        }
+        let p = map.index_to_point2d(idx);
+        let chunk = spawn_chunk(map, p);
+        if chunk != arrival {
+            regions[chunk as usize].push(p);
+        }
+    }
+    // Leave out slivers that are barely a corridor
+    regions.retain(|r| r.len() > (SPAWN_CHUNK * SPAWN_CHUNK / 4) as usize);
+    regions
+}
+
+// A distance map from the starting position over everything walkable.
+// Unreachable tiles are left at f32::MAX.
+fn distances_from_start(map: &Map) -> rltk::DijkstraMap {
+    let start = map.point2d_to_index(find_starting_position(map));
+    let mut dijkstra = rltk::DijkstraMap::new(
+        map.width,
+        map.height,
+        &[start],
+        map,
+        (map.width * map.height) as f32,
+    );
+    // rltk only fills in tiles it walks into, which doesn't include the start
+    dijkstra.map[start] = 0.0;
+    dijkstra
+}
+
+// The way down is in the last room, or on maps without rooms as far from
+// the start as you can walk. The first level has no way up, every other
+// level puts it where the player arrives.
+pub fn add_stairs(map: &mut Map) {
+    let start = find_starting_position(map);
+    // With a single room the last room is also the first, so the stairs
+    // down go to the far corner of it instead of under the player
+    let down = match map.rooms.last().map(|last| last.center()) {
+        Some(centre) if centre != start => Some(centre),
+        _ => {
+            let dijkstra = distances_from_start(map);
+            dijkstra
+                .map
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| **d > 0.0 && **d < f32::MAX)
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(idx, _)| map.index_to_point2d(idx))
         }
+    };
+    if let Some(down) = down {
+        let idx = map.xy_idx(down.x, down.y);
+        map.tiles[idx] = TileType::DownStairs;
     }
-    starting_position
+    if map.depth > 1 {
+        let idx = map.xy_idx(start.x, start.y);
+        map.tiles[idx] = TileType::UpStairs;
+    }
+}
+
+// Indexes into map.rooms of every room whose centre can't be walked to
+// from the starting position
+pub fn unreachable_rooms(map: &Map) -> Vec<usize> {
+    let dijkstra = distances_from_start(map);
+    map.rooms
+        .iter()
+        .enumerate()
+        .filter(|(_, room)| dijkstra.map[map.point2d_to_index(room.center())] == f32::MAX)
+        .map(|(i, _)| i)
+        .collect()
 }
 
-pub fn make_dungeon(mgc: &MapGenConfig, rng: &mut RandomNumberGenerator, map: &mut Map) {
-    let (rooms, cors) = generate_rooms_and_corridors(mgc, rng);
+// Digs an L shaped tunnel, across then down, lined with corridor walls
+// wherever it passes through empty space
+pub fn dig_corridor(map: &mut Map, from: Point, to: Point) {
+    let mut path = vec![];
+    for x in i32::min(from.x, to.x)..=i32::max(from.x, to.x) {
+        path.push(Point::new(x, from.y));
+    }
+    for y in i32::min(from.y, to.y)..=i32::max(from.y, to.y) {
+        path.push(Point::new(to.x, y));
+    }
+
+    for p in path.iter() {
+        let idx = map.xy_idx(p.x, p.y);
+        if map.tiles[idx].is_blocker() || map.tiles[idx] == TileType::Empty {
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+    for p in path.iter() {
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            let side = Point::new(p.x + dx, p.y + dy);
+            if !map.in_bounds(side) {
+                continue;
+            }
+            let idx = map.point2d_to_index(side);
+            if map.tiles[idx] == TileType::Empty {
+                map.tiles[idx] = TileType::CorWall;
+            }
+        }
+    }
+}
+
+// connect_some_rooms can leave rooms stranded, so join each unreachable
+// room to the closest room we can already get to. Returns false if some
+// rooms still can't be reached afterwards.
+pub fn repair_connectivity(map: &mut Map) -> bool {
+    for _ in 0..map.rooms.len() {
+        let unreachable = unreachable_rooms(map);
+        let Some(stranded) = unreachable.first() else {
+            return true;
+        };
+        let from = map.rooms[*stranded].center();
+        let closest = map
+            .rooms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !unreachable.contains(i))
+            .map(|(_, room)| room.center())
+            .min_by_key(|c| (c.x - from.x).abs() + (c.y - from.y).abs());
+        match closest {
+            Some(to) => dig_corridor(map, from, to),
+            None => return false,
+        }
+    }
+    unreachable_rooms(map).is_empty()
+}
+
+pub fn make_cave(rng: &mut RandomNumberGenerator, map: &mut Map, fill_percent: i32, iterations: i32) {
+    // Start with noise, keeping a solid border around the edge
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let border = x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1;
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = if border || rng.roll_dice(1, 100) <= fill_percent {
+                TileType::Wall
+            } else {
+                TileType::Floor
+            };
+        }
+    }
+
+    // Each pass a tile becomes a wall if most of its neighbours are walls,
+    // or if it is sitting alone in open space
+    for _ in 0..iterations {
+        let mut next = map.tiles.clone();
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let mut walls = 0;
+                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    if map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall {
+                        walls += 1;
+                    }
+                }
+                next[map.xy_idx(x, y)] = if walls > 4 || walls == 0 {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+            }
+        }
+        map.tiles = next;
+    }
+
+    cull_unreachable(map);
+}
+
+// Walls off any pocket of floor that can't be walked to from the start
+pub fn cull_unreachable(map: &mut Map) {
+    let dijkstra = distances_from_start(map);
+    for (idx, distance) in dijkstra.map.iter().enumerate() {
+        if *distance == f32::MAX && map.tiles[idx] == TileType::Floor {
+            map.tiles[idx] = TileType::Wall;
+        }
+    }
+}
+
+pub fn find_tile(map: &Map, tile_type: TileType) -> Option<Point> {
+    map.tiles
+        .iter()
+        .position(|t| *t == tile_type)
+        .map(|idx| map.index_to_point2d(idx))
+}
+
+pub fn make_dungeon(
+    mgc: &MapGenConfig,
+    rng: &mut RandomNumberGenerator,
+    map: &mut Map,
+) -> Result<(), MapGenError> {
+    mgc.validate()?;
+    let uses_rooms = !matches!(mgc.generator, MapGenerator::Cave { .. });
+    if uses_rooms && (mgc.max_room_x > map.width || mgc.max_room_y > map.height) {
+        return Err(MapGenError::LargerThanMap {
+            config: Point::new(mgc.max_room_x, mgc.max_room_y),
+            map: Point::new(map.width, map.height),
+        });
+    }
+    let (rooms, cors) = match mgc.generator {
+        MapGenerator::RoomsAndCorridors => generate_rooms_and_corridors(mgc, rng)?,
+        MapGenerator::Bsp => generate_bsp_rooms_and_corridors(mgc, rng)?,
+        MapGenerator::Cave { fill_percent, iterations } => {
+            make_cave(rng, map, fill_percent, iterations);
+            // Need somewhere to stand and somewhere else for the stairs
+            let floor = map.tiles.iter().filter(|t| **t == TileType::Floor).count();
+            if floor < 2 {
+                return Err(MapGenError::NotEnoughFloor { floor });
+            }
+            add_stairs(map);
+            map.populate_blocked();
+            return Ok(());
+        }
+    };
     map.rooms = rooms.clone();
     make_map_of_rooms_and_corridors(map, rooms, cors);
     add_doors(map);
+    if !repair_connectivity(map) {
+        return Err(MapGenError::Disconnected { unreachable: unreachable_rooms(map).len() });
+    }
+    add_stairs(map);
     map.populate_blocked();
+    Ok(())
 }
 
 impl Algorithm2D for Map {
@@ -537,11 +1012,13 @@ impl Algorithm2D for Map {
 }
 
 impl Map {
+    pub fn xy_idx(&self, x: i32, y: i32) -> usize {
+        self.point2d_to_index(Point::new(x, y))
+    }
+
     pub fn populate_blocked(&mut self) {
-        for (i, tile) in self.tiles.iter_mut().enumerate() {
-            if tile.is_blocker() {
-                self.blocked[i] = true;
-            }
+        for (i, tile) in self.tiles.iter().enumerate() {
+            self.blocked[i] = tile.is_blocker();
         }
     }
 
@@ -561,18 +1038,25 @@ impl Map {
         self.tiles[idx].is_opaque()
     }
 
+    // Nothing opaque between the two, whatever is on either end
+    pub fn line_of_fire(&self, from: Point, to: Point) -> bool {
+        rltk::line2d(rltk::LineAlg::Bresenham, from, to)
+            .into_iter()
+            .filter(|p| *p != from && *p != to)
+            .all(|p| self.in_bounds(p) && !self.is_opaque(self.point2d_to_index(p)))
+    }
+
     pub fn is_exit_valid(&self, x: i32, y: i32) -> bool {
-        let idx = xy_idx(x, y);
         if x < 1 || x > self.width - 2 || y < 1 || y > self.height - 2 {
             return false;
         }
-        !self.is_opaque(idx)
+        !self.is_opaque(self.xy_idx(x, y))
     }
 }
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        return self.is_opaque(idx);
+        self.is_opaque(idx)
     }
 
     fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
@@ -603,3 +1087,193 @@ impl BaseMap for Map {
         rltk::DistanceAlg::Pythagoras.distance2d(p1, p2)
     }
 }
+
+#[cfg(test)]
+prop_compose! {
+    fn arb_map_and_point()(width in 1..300usize, height in 1..300usize)
+                          (x in 0..width as i32,
+                           y in 0..height as i32,
+                           width in Just(width),
+                           height in Just(height)) -> (Map, Point) {
+        (new_map(width, height), Point::new(x, y))
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_xy_idx_round_trip((map, p) in arb_map_and_point()) {
+        let idx = map.xy_idx(p.x, p.y);
+        prop_assert!(idx < map.tiles.len());
+        prop_assert_eq!(idx, map.point2d_to_index(p));
+        prop_assert_eq!(map.index_to_point2d(idx), p);
+        prop_assert!(map.in_bounds(p));
+    }
+
+    #[test]
+    fn test_index_round_trip(width in 1..120usize, height in 1..120usize) {
+        let map = new_map(width, height);
+        for idx in 0..map.tiles.len() {
+            let p = map.index_to_point2d(idx);
+            prop_assert!(map.in_bounds(p));
+            prop_assert_eq!(map.xy_idx(p.x, p.y), idx);
+        }
+    }
+
+    #[test]
+    fn test_make_dungeon_any_size(width in 40..200usize, height in 30..120usize, seed in any::<u64>()) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(width, height);
+        let mut mgc = default_map_config();
+        mgc.max_room_width = 10;
+        mgc.max_room_height = 10;
+        mgc.max_room_x = width as i32 - 2;
+        mgc.max_room_y = height as i32 - 2;
+        mgc.num_rooms = 3;
+
+        make_dungeon(&mgc, &mut rng, &mut map).unwrap();
+        for room in map.rooms.iter() {
+            prop_assert!(map.in_bounds(room.center()));
+        }
+        prop_assert_eq!(map.tiles.len(), width * height);
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_cave_is_fully_reachable(fill_percent in 35..55, iterations in 0..8, seed in any::<u64>()) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(60, 40);
+        let mut mgc = default_map_config();
+        mgc.generator = MapGenerator::Cave { fill_percent, iterations };
+
+        // Unsmoothed noise can leave the start sealed in on its own
+        match make_dungeon(&mgc, &mut rng, &mut map) {
+            Err(MapGenError::NotEnoughFloor { .. }) => return Ok(()),
+            built => prop_assert_eq!(built, Ok(())),
+        }
+        prop_assert!(map.rooms.is_empty());
+        let start = find_starting_position(&map);
+        prop_assert!(!map.tiles[map.xy_idx(start.x, start.y)].is_blocker());
+
+        let dijkstra = distances_from_start(&map);
+        for (idx, tile) in map.tiles.iter().enumerate() {
+            if !tile.is_blocker() {
+                prop_assert!(dijkstra.map[idx] < f32::MAX);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_every_room_is_reachable(
+        seed in any::<u64>(),
+        generator in prop_oneof![Just(MapGenerator::RoomsAndCorridors), Just(MapGenerator::Bsp)],
+    ) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(80, 50);
+        let mut mgc = default_map_config();
+        mgc.generator = generator;
+
+        make_dungeon(&mgc, &mut rng, &mut map).unwrap();
+        let dijkstra = distances_from_start(&map);
+        let start = map.point2d_to_index(map.rooms[0].center());
+        prop_assert_eq!(dijkstra.map[start], 0.0);
+        for room in map.rooms.iter() {
+            prop_assert!(dijkstra.map[map.point2d_to_index(room.center())] < f32::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+prop_compose! {
+    // Anything goes, including configs that can't possibly work
+    fn arb_any_map_gen_config()(max_room_width in -5..40,
+                                max_room_height in -5..40,
+                                min_room_width in -5..40,
+                                min_room_height in -5..40,
+                                min_room_x in -5..60,
+                                min_room_y in -5..60,
+                                max_room_x in -5..100,
+                                max_room_y in -5..100,
+                                num_rooms in 0..30usize,
+                                corridor_size in -1..5,
+                                room_max_connections in 0..4usize,
+                                generator in prop_oneof![
+                                    Just(MapGenerator::RoomsAndCorridors),
+                                    Just(MapGenerator::Bsp),
+                                    (-10..110, -2..6).prop_map(|(fill_percent, iterations)|
+                                        MapGenerator::Cave { fill_percent, iterations }),
+                                ],
+                                max_room_attempts in 0..2_000usize) -> MapGenConfig {
+        MapGenConfig {
+            max_room_width,
+            max_room_height,
+            min_room_width,
+            min_room_height,
+            min_room_x,
+            min_room_y,
+            max_room_x,
+            max_room_y,
+            num_rooms,
+            corridor_size,
+            room_max_connections,
+            generator,
+            max_room_attempts,
+        }
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_make_dungeon_never_panics_or_hangs(mgc in arb_any_map_gen_config(), seed in any::<u64>()) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(80, 50);
+        match make_dungeon(&mgc, &mut rng, &mut map) {
+            Ok(()) => {
+                prop_assert!(mgc.validate().is_ok());
+                prop_assert!(find_tile(&map, TileType::DownStairs).is_some());
+            }
+            Err(e) => prop_assert!(!e.to_string().is_empty()),
+        }
+    }
+
+    #[test]
+    fn test_deeper_levels_have_stairs_both_ways(
+        mgc in arb_any_map_gen_config(),
+        seed in any::<u64>(),
+        depth in 2..10,
+    ) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(80, 50);
+        map.depth = depth;
+        if make_dungeon(&mgc, &mut rng, &mut map).is_ok() {
+            prop_assert!(find_tile(&map, TileType::DownStairs).is_some());
+            prop_assert_eq!(find_tile(&map, TileType::UpStairs), Some(find_starting_position(&map)));
+        }
+    }
+
+    #[test]
+    fn test_a_lone_room_still_has_stairs_both_ways(seed in any::<u64>(), depth in 2..10) {
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        let mut map = new_map(80, 50);
+        map.depth = depth;
+        let mut mgc = default_map_config();
+        mgc.num_rooms = 1;
+        make_dungeon(&mgc, &mut rng, &mut map).unwrap();
+        prop_assert_eq!(map.rooms.len(), 1);
+        prop_assert!(find_tile(&map, TileType::DownStairs).is_some());
+        prop_assert!(find_tile(&map, TileType::UpStairs).is_some());
+    }
+
+    #[test]
+    fn test_default_config_is_valid(seed in any::<u64>()) {
+        prop_assert_eq!(default_map_config().validate(), Ok(()));
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        prop_assert!(make_dungeon(&default_map_config(), &mut rng, &mut new_map(80, 50)).is_ok());
+    }
+}
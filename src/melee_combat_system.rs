@@ -1,5 +1,10 @@
-use crate::{components::{CombatStats, Name, SufferDamage, WantsToMelee}, gamelog::GameLog};
-use rltk::console;
+use crate::{
+    components::{
+        CombatStats, DefenseBonus, Equipped, GainsEffect, Inflicts, MeleePowerBonus, Name,
+        SufferDamage, WantsToMelee,
+    },
+    gamelog::GameLog,
+};
 use specs::prelude::*;
 
 pub struct MeleeCombatSystem {}
@@ -12,6 +17,11 @@ impl<'a> System<'a> for MeleeCombatSystem {
         WriteStorage<'a, SufferDamage>,
         WriteExpect<'a, GameLog>,
         ReadStorage<'a, Name>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, MeleePowerBonus>,
+        ReadStorage<'a, DefenseBonus>,
+        ReadStorage<'a, Inflicts>,
+        WriteStorage<'a, GainsEffect>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -20,23 +30,57 @@ impl<'a> System<'a> for MeleeCombatSystem {
             mut wants_melee, 
             mut inflict_damage, 
             mut game_log,
-            names) = data;
+            names,
+            equipped,
+            power_bonus,
+            defense_bonus,
+            inflicts,
+            mut gains_effect) = data;
 
-        for (_entity, stats, wants_melee, name) in (&entities, &c_stats, &wants_melee, &names).join() {
+        for (entity, stats, wants_melee, name) in (&entities, &c_stats, &wants_melee, &names).join() {
             let target_stats = c_stats.get(wants_melee.target).unwrap();
             if target_stats.hp > 0 {
                 let target_name = names.get(wants_melee.target).unwrap();    
-                let damage = i32::max(0, stats.power - target_stats.defense);
+                let (power, _) = effective_stats(entity, stats, &equipped, &power_bonus, &defense_bonus);
+                let (_, defense) = effective_stats(wants_melee.target, target_stats, &equipped, &power_bonus, &defense_bonus);
+                let damage = i32::max(0, power - defense);
                 if damage == 0 {
                     game_log.entries.push(format!("{} is unable to hurt {}", &name.name, &target_name.name));
                 } else {
                     game_log.entries.push(
                         format!("{} attacks {} for {} hit points.",
                         &name.name, &target_name.name, damage));
-                    SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                    SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, Some(entity));
+                    // Venom and the like only get in through a wound
+                    if let Some(inflicts) = inflicts.get(entity) {
+                        GainsEffect::new_effect(&mut gains_effect, wants_melee.target, inflicts.inflicted_by(Some(entity)));
+                    }
                 }
             }
         }
         wants_melee.clear();
     }
 }
+
+// Power and defense with everything `owner` wears added on
+pub fn effective_stats(
+    owner: Entity,
+    stats: &CombatStats,
+    equipped: &ReadStorage<Equipped>,
+    power_bonus: &ReadStorage<MeleePowerBonus>,
+    defense_bonus: &ReadStorage<DefenseBonus>,
+) -> (i32, i32) {
+    let mut power = stats.power;
+    let mut defense = stats.defense;
+    for (worn, bonus) in (equipped, power_bonus).join() {
+        if worn.owner == owner {
+            power += bonus.power;
+        }
+    }
+    for (worn, bonus) in (equipped, defense_bonus).join() {
+        if worn.owner == owner {
+            defense += bonus.defense;
+        }
+    }
+    (power, defense)
+}
@@ -1,206 +1,172 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rand::seq::SliceRandom;
 use rltk::{RandomNumberGenerator, RGB};
+use specs::prelude::*;
 use rouge::FromSpec;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    components::{Name, Position, Renderable, CombatStats},
-    map::Map,
-    state::{create_monster, State, create_spawner},
+    components::{
+        BlocksTile, CombatStats, Energy, Inflicts, Monster, Name, Position, Renderable, Spawner,
+        Viewshed,
+    },
+    item::ItemSpec,
+    map::{spawn_regions, Map},
+    raws::Raws,
+    state::State,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize, FromSpec)]
+#[spec(with = BlocksTile {})]
+#[spec(with = Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })]
+#[spec(with = Monster { mobile: true })]
+#[spec(default = Energy)]
 pub struct MonsterSpec {
-    glyph: rltk::FontCharType,
-    name: String,
-    fg: RGB,
-    bg: RGB,
-    point: rltk::Point,
-    combat_stats: Option<CombatStats>,
+    #[spec(into = Renderable)]
+    pub(crate) glyph: rltk::FontCharType,
+    #[spec(into = Name)]
+    pub(crate) name: String,
+    #[spec(into = Renderable)]
+    pub(crate) fg: RGB,
+    #[spec(into = Renderable)]
+    pub(crate) bg: RGB,
+    #[spec(into = Position)]
+    pub(crate) point: rltk::Point,
+    #[spec(component)]
+    pub(crate) combat_stats: Option<CombatStats>,
+    // 100 acts as often as the player, 200 twice as often
+    #[spec(into = Energy)]
+    pub(crate) speed: i32,
+    // Worn from the start, see MonsterSpec::build_with_gear
+    pub(crate) equipment: Vec<ItemSpec>,
+    // Given to whoever its attacks wound
+    #[spec(component)]
+    pub(crate) inflicts: Option<Inflicts>,
 }
 
+// Spawners stay put and make monsters from spawn_spec
 #[derive(Clone, Debug, FromSpec)]
+#[spec(with = BlocksTile {})]
+#[spec(with = Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })]
+#[spec(with = Monster { mobile: false })]
+#[spec(default = Energy, from = Spawner)]
 pub struct SpawnerSpec {
-    glyph: rltk::FontCharType,
-    name: String,
-    fg: RGB,
-    bg: RGB,
-    point: rltk::Point,
-    combat_stats: CombatStats,
-    spawn_spec: MonsterSpec,
-    spawn_per: i32,
-    spawn_max: i32,
+    #[spec(into = Renderable)]
+    pub(crate) glyph: rltk::FontCharType,
+    #[spec(into = Name)]
+    pub(crate) name: String,
+    #[spec(into = Renderable)]
+    pub(crate) fg: RGB,
+    #[spec(into = Renderable)]
+    pub(crate) bg: RGB,
+    #[spec(into = Position)]
+    pub(crate) point: rltk::Point,
+    #[spec(component)]
+    pub(crate) combat_stats: CombatStats,
+    pub(crate) spawn_spec: MonsterSpec,
+    // How many of its own turns it takes to spawn one child
+    pub(crate) spawn_per: i32,
+    pub(crate) spawn_max: i32,
+    #[spec(into = Energy)]
+    pub(crate) speed: i32,
 }
 
-impl From<MonsterSpec> for Position {
-    fn from(val: MonsterSpec) -> Self {
-        Position { point: val.point }
+impl MonsterSpec {
+    pub fn with_point(mut self, point: rltk::Point) -> MonsterSpec {
+        self.point = point;
+        self
     }
-}
 
-impl From<MonsterSpec> for Renderable {
-    fn from(val: MonsterSpec) -> Renderable {
-        Renderable {
-            glyph: val.glyph,
-            fg: val.fg,
-            bg: val.bg,
+    // build_entity, with the monster wearing its equipment
+    pub fn build_with_gear(&self, ecs: &mut World) -> Entity {
+        let monster = self.build_entity(ecs);
+        for item in self.equipment.iter() {
+            item.equipped_by(ecs.create_entity(), monster).build();
         }
+        monster
     }
 }
 
-impl From<MonsterSpec> for Option<CombatStats> {
-    fn from(val: MonsterSpec) -> Option<CombatStats> {
-        val.combat_stats
+impl SpawnerSpec {
+    pub fn with_point(mut self, point: rltk::Point) -> SpawnerSpec {
+        self.point = point;
+        self
     }
 }
 
-impl From<MonsterSpec> for Name {
-    fn from(val: MonsterSpec) -> Name {
-        Name { name: val.name }
+impl From<SpawnerSpec> for Spawner {
+    fn from(val: SpawnerSpec) -> Spawner {
+        Spawner {
+            rate: 1.0 / i32::max(1, val.spawn_per) as f32,
+            progress: 0.0,
+            spec: val.spawn_spec,
+            max_children: val.spawn_max,
+            children: 0,
+        }
     }
 }
 
-pub trait MonsterGenerator<T> {
-    fn gen_one(&self, rng: &mut RandomNumberGenerator) -> T;
-    fn gen_one_with_pos(&self, rng: &mut RandomNumberGenerator, x: i32, y: i32) -> T ;
-}
-
-fn default_monsters() -> Vec<MonsterSpec> {
-    vec![
-        MonsterSpec {
-            glyph: rltk::to_cp437('r'),
-            name: "Repressionist".to_string(),
-            fg: RGB::named(rltk::RED),
-            bg: RGB::named(rltk::BLACK),
-            point: rltk::Point { x: 0, y: 0 },
-            combat_stats: Some(CombatStats {
-                max_hp: 16,
-                hp: 16,
-                defense: 1,
-                power: 4})
-        },
-        MonsterSpec {
-            glyph: rltk::to_cp437('o'),
-            name: "Orgy Hunter".to_string(),
-            fg: RGB::named(rltk::RED),
-            bg: RGB::named(rltk::BLACK),
-            point: rltk::Point { x: 0, y: 0 },
-            combat_stats: Some(CombatStats {
-                max_hp: 16,
-                hp: 16,
-                defense: 1,
-                power: 4})
-        },
-    ]
-}
-
-fn default_spawners() -> Vec<SpawnerSpec>{
-    vec![
-    SpawnerSpec {
-        glyph: rltk::to_cp437('P'),
-        name: "Orc Spawner".to_string(),
-        fg: RGB::named(rltk::RED),
-        bg: RGB::named(rltk::BLACK),
-        point: rltk::Point { x: 0, y: 0 },
-        combat_stats: CombatStats {
-            max_hp: 2,
-            hp: 2,
-            defense: 1,
-            power: 1},
-        spawn_max: 5,
-        spawn_per: 1,
-        spawn_spec:
-        MonsterSpec {
-            // I thought s was the right character, but it's not
-            // instead this is a list of ideas:
-            // simley face emoji (😀)
-            glyph: rltk::to_cp437('p'),
-            name: "Orc Spawnling".to_string(),
-            fg: RGB::named(rltk::RED),
-            bg: RGB::named(rltk::BLACK),
-            point: rltk::Point { x: 0, y: 0 },
-            combat_stats: Some(CombatStats {
-                max_hp: 2,
-                hp: 2,
-                defense: 1,
-                power: 1}),
+// Fills every room but the one the player arrives in from the spawn
+// tables for the map's depth. Nothing is placed on top of anything else.
+pub fn populate_rooms(gs: &mut State, rng: &mut RandomNumberGenerator, map: &Map) {
+    let raws = (*gs.ecs.fetch::<Raws>()).clone();
+
+    for region in spawn_regions(map).iter() {
+        let mut free = region.clone();
+        free.shuffle(rng.get_rng());
+
+        let monsters = raws.monsters_per_room.roll(rng);
+        let spawners = raws.spawners_per_room.roll(rng);
+        let items = raws.items_per_room.roll(rng);
+        for _ in 0..monsters {
+            let Some(point) = free.pop() else { break };
+            if let Some(spec) = raws.monster_table.roll(rng, map.depth) {
+                spec.with_point(point).build_with_gear(&mut gs.ecs);
+            }
         }
-    },
-       SpawnerSpec {
-        glyph: rltk::to_cp437('C'),
-        name: "Cow Spawner".to_string(),
-        fg: RGB::named(rltk::RED),
-        bg: RGB::named(rltk::BLACK),
-        point: rltk::Point { x: 0, y: 0 },
-        combat_stats: CombatStats {
-            max_hp: 2,
-            hp: 2,
-            defense: 1,
-            power: 1},
-        spawn_max: 5,
-        spawn_per: 1,
-        spawn_spec:
-        MonsterSpec {
-            // I thought s was the right character, but it's not
-            // instead this is a list of ideas:
-            // simley face emoji ()
-            glyph: rltk::to_cp437('c'),
-            name: "Cow Spawnling".to_string(),
-            fg: RGB::named(rltk::RED),
-            bg: RGB::named(rltk::BLACK),
-            point: rltk::Point { x: 0, y: 0 },
-            combat_stats: Some(CombatStats {
-                max_hp: 2,
-                hp: 2,
-                defense: 1,
-                power: 1}),
+        for _ in 0..spawners {
+            let Some(point) = free.pop() else { break };
+            if let Some(spec) = raws.spawner_table.roll(rng, map.depth) {
+                spec.with_point(point).build_entity(&mut gs.ecs);
+            }
+        }
+        for _ in 0..items {
+            let Some(point) = free.pop() else { break };
+            if let Some(spec) = raws.item_table.roll(rng, map.depth) {
+                spec.with_point(point).build_entity(&mut gs.ecs);
+            }
         }
-    }  
-    ]
-}
-
-pub struct DefaultMonsterGenerator;
-
-impl MonsterGenerator<MonsterSpec> for DefaultMonsterGenerator {
-    fn gen_one(&self, rng: &mut RandomNumberGenerator) -> MonsterSpec {
-        let len = (default_monsters().len() - 1) as i32;
-        let i = rng.roll_dice(1, len);
-        default_monsters()[i as usize].clone()
-    }
-    fn gen_one_with_pos(&self, rng: &mut RandomNumberGenerator, x: i32, y: i32) -> MonsterSpec {
-        let mut spec = self.gen_one(rng);
-        spec.point.x = x;
-        spec.point.y = y;
-        spec
-    }
-}
-
-pub struct DefaultSpawnerGenerator;
-
-impl MonsterGenerator<SpawnerSpec> for DefaultSpawnerGenerator {
-    fn gen_one(&self, rng: &mut RandomNumberGenerator) -> SpawnerSpec{
-        let len = (default_spawners().len() - 1) as i32;
-        let i = rng.roll_dice(1, len);
-        default_spawners()[i as usize].clone()
-    }
-    fn gen_one_with_pos(&self, rng: &mut RandomNumberGenerator, x: i32, y: i32) -> SpawnerSpec {
-        let mut spec = self.gen_one(rng);
-        spec.point.x = x;
-        spec.point.y = y;
-        spec
     }
 }
 
-pub fn generate_monsters(gs: &mut State, rng: &mut RandomNumberGenerator, map: &Map) {
-    let gen = DefaultMonsterGenerator;
-    let spawn_gen = DefaultSpawnerGenerator;
-
-    for room in map.rooms.iter().skip(1) {
-        let (x, y) = room.center().to_tuple();
-        create_monster(gs, gen.gen_one_with_pos(rng, x, y));
-        create_spawner(gs, spawn_gen.gen_one_with_pos(rng, x-1, y-1));
-        // one out of 5 rooms add a spawner
-        // if rng.roll_dice(1, 5) == 1 {
-        //     // All rooms are bigger thin 3x3 so this will be fine :P
-        //     spawn_gen.gen_one_with_pos(rng, x-1, y-1);
-        // }
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_nothing_waits_where_the_player_arrives(seed in any::<u64>(), depth in 2..8i32, caves in any::<bool>()) {
+        use crate::components::Position;
+        use crate::map::{default_map_config, in_arrival_area, MapGenerator};
+
+        let mut mgc = default_map_config();
+        if caves {
+            mgc.generator = MapGenerator::Cave { fill_percent: 45, iterations: 5 };
+        }
+        let mut gs = State::new_run(seed, mgc, crate::raws::test_raws(), 80, 50).unwrap();
+        // Only what the new level brings along is of interest
+        gs.ecs.delete_all();
+        gs.build_level(depth).unwrap();
+        gs.ecs.maintain();
+
+        let map = gs.ecs.fetch::<Map>();
+        let positions = gs.ecs.read_storage::<Position>();
+        let monsters = gs.ecs.read_storage::<Monster>();
+        let spawners = gs.ecs.read_storage::<Spawner>();
+        for (pos, _) in (&positions, &monsters).join() {
+            prop_assert!(!in_arrival_area(&map, pos.point), "a monster waits at {:?}", pos.point);
+        }
+        for (pos, _) in (&positions, &spawners).join() {
+            prop_assert!(!in_arrival_area(&map, pos.point), "a spawner waits at {:?}", pos.point);
+        }
     }
 }
@@ -1,77 +1,85 @@
 use crate::{
-    components::{Monster, Name, Position, Viewshed, WantsToMelee},
-    map::{xy_idx, Map},
+    action::{Action, ActionResolver},
+    components::{Monster, MyTurn},
     state::RunState,
+    targeting::can_fire,
 };
-use rltk::{console, Algorithm2D, Point};
+use rltk::{console, Algorithm2D};
 use specs::prelude::*;
 
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
     type SystemData = (
-        Entities<'a>,
+        ActionResolver<'a>,
         ReadExpect<'a, RunState>,
-        ReadExpect<'a, Entity>, // The player entity
-        WriteExpect<'a, Map>,
         ReadStorage<'a, Monster>,
-        WriteStorage<'a, Viewshed>,
-        WriteStorage<'a, Position>,
-        ReadStorage<'a, Name>,
-        ReadExpect<'a, Point>,
-        WriteStorage<'a, WantsToMelee>,
+        ReadStorage<'a, MyTurn>,
     );
     fn run(&mut self, data: Self::SystemData) {
-        let (
-            entities,
-            runstate,
-            player,
-            mut map,
-            monsters,
-            mut viewshed,
-            mut pos,
-            name,
-            player_pos,
-            mut wants_to_melee,
-        ) = data;
+        let (mut resolver, runstate, monsters, my_turn) = data;
 
-        if *runstate != RunState::MonsterTurn {
+        if *runstate != RunState::Ticking {
             return;
         }
 
-        for (ent, monster, mut viewshed, name, mut pos) in
-            (&entities, &monsters, &mut viewshed, &name, &mut pos).join()
-        {
-            if viewshed.visible_tiles.contains(&*player_pos) {
-                console::log(&format!("{} leers at you", name.name));
-                // Check to see if the player is close enough to attack
-                let distance = rltk::DistanceAlg::Pythagoras.distance2d(pos.point, *player_pos);
+        // Decided and resolved one at a time, so each monster sees where
+        // the ones before it moved to
+        let acting: Vec<(Entity, bool)> = (&resolver.entities, &monsters, &my_turn)
+            .join()
+            .map(|(ent, monster, _turn)| (ent, monster.mobile))
+            .collect();
 
-                // diagonal attack works too
-                if distance < 1.5 {
-                    wants_to_melee
-                        .insert(ent, WantsToMelee { target: *player })
-                        .expect("Unagle to insert attack");
-                }
-
-                if !monster.mobile  { continue;}
+        for (ent, mobile) in acting {
+            let action = decide(&resolver, ent, mobile);
+            resolver.resolve(ent, action);
+        }
+    }
+}
 
-                let path = rltk::a_star_search(
-                    xy_idx(pos.point.x, pos.point.y),
-                    xy_idx(player_pos.x, player_pos.y),
-                    &mut *map,
-                );
+fn decide(resolver: &ActionResolver, ent: Entity, mobile: bool) -> Action {
+    let player_pos = *resolver.player_pos;
+    let (Some(viewshed), Some(pos)) = (resolver.viewsheds.get(ent), resolver.positions.get(ent)) else {
+        return Action::Wait;
+    };
+    if !viewshed.visible_tiles.contains(&player_pos) {
+        return Action::Wait;
+    }
+    if let Some(name) = resolver.names.get(ent) {
+        console::log(format!("{} leers at you", name.name));
+    }
 
-                if path.success && path.steps.len() > 2 {
-                    let next_point = map.index_to_point2d(path.steps[1]);
-                    let last_point = map.point2d_to_index(pos.point);
-                    map.blocked[last_point] = false;
-                    pos.point.x = next_point.x;
-                    pos.point.y = next_point.y;
-                    map.blocked[path.steps[1]] = true;
-                    viewshed.dirty = true;
-                }
-            }
+    // diagonal attack works too
+    let distance = rltk::DistanceAlg::Pythagoras.distance2d(pos.point, player_pos);
+    if distance < 1.5 {
+        return Action::Attack(*resolver.player);
+    }
+    // Anyone with a ranged weapon shoots rather than closes in
+    if let Some(weapon) = resolver.ranged_weapon(ent) {
+        let range = resolver.ranged.get(weapon).map_or(0, |r| r.range);
+        if can_fire(&resolver.map, pos.point, player_pos, range) {
+            return Action::Shoot {
+                with: weapon,
+                target: *resolver.player,
+            };
         }
     }
+    if !mobile {
+        return Action::Wait;
+    }
+
+    let map = &*resolver.map;
+    let path = rltk::a_star_search(
+        map.point2d_to_index(pos.point),
+        map.point2d_to_index(player_pos),
+        map,
+    );
+    if path.success && path.steps.len() > 2 {
+        let next = map.index_to_point2d(path.steps[1]);
+        return Action::Move {
+            dx: next.x - pos.point.x,
+            dy: next.y - pos.point.y,
+        };
+    }
+    Action::Wait
 }
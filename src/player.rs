@@ -1,76 +1,182 @@
-use crate::components::*;
-use crate::map::*;
+use crate::action::{Action, ActionResolver, ActionResult};
+use crate::components::{CombatStats, Equippable, Experience, Ranged, Throwable};
+use crate::gamelog::GameLog;
+use crate::inventory_system::backpack_of;
 use crate::state::{RunState, State};
-use rltk::{Point, Rltk, VirtualKeyCode};
+use crate::targeting::{key_target, targets, Targeting};
+use rltk::VirtualKeyCode;
 use specs::prelude::*;
-use std::cmp::{max, min};
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
-    let mut positions = ecs.write_storage::<Position>();
-    let mut players = ecs.write_storage::<Player>();
-    let mut viewsheds = ecs.write_storage::<Viewshed>();
-    let mut point = ecs.write_resource::<Point>();
-    let entities = ecs.entities();
-    let combat_stats = ecs.read_storage::<CombatStats>();
-    let map = ecs.fetch::<Map>();
-    let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
-
-    for (entity, _player, pos, viewshed) in
-        (&entities, &mut players, &mut positions, &mut viewsheds).join()
-    {
-        // build the new position
-        let x = min(78, max(0, pos.point.x + delta_x));
-        let y = min(48, max(0, pos.point.y + delta_y));
+// What a key asks the player to do. Keys that aren't actions in the game,
+// like saving or revealing the map, are handled by player_input itself.
+pub fn key_action(key: VirtualKeyCode) -> Option<Action> {
+    match key {
+        VirtualKeyCode::H => Some(Action::Move { dx: -1, dy: 0 }),
+        VirtualKeyCode::L => Some(Action::Move { dx: 1, dy: 0 }),
+        VirtualKeyCode::K => Some(Action::Move { dx: 0, dy: -1 }),
+        VirtualKeyCode::J => Some(Action::Move { dx: 0, dy: 1 }),
+        VirtualKeyCode::U => Some(Action::Move { dx: 1, dy: -1 }),
+        VirtualKeyCode::Y => Some(Action::Move { dx: -1, dy: -1 }),
+        VirtualKeyCode::M => Some(Action::Move { dx: 1, dy: 1 }),
+        VirtualKeyCode::N => Some(Action::Move { dx: -1, dy: 1 }),
+        VirtualKeyCode::Space => Some(Action::Wait),
+        VirtualKeyCode::G => Some(Action::PickUp),
+        VirtualKeyCode::Period | VirtualKeyCode::Comma => Some(Action::UseStairs),
+        _ => None,
+    }
+}
 
-        let dest_idx = xy_idx(x, y);
+pub fn player_action(ecs: &mut World, action: Action) -> RunState {
+    let player = *ecs.fetch::<Entity>();
+    let result = ecs.system_data::<ActionResolver>().resolve(player, action);
+    match result {
+        ActionResult::Done => RunState::PlayerTurn,
+        ActionResult::Nothing => RunState::AwaitingInput,
+        ActionResult::Descend => RunState::NextLevel,
+        ActionResult::Ascend => RunState::PreviousLevel,
+    }
+}
 
-        for potential_target in map.tile_content[dest_idx].iter() {
-            let target = combat_stats.get(*potential_target);
-            match target {
-                None => {}
-                Some(_t) => {
-                    wants_to_melee
-                        .insert(
-                            entity,
-                            WantsToMelee {
-                                target: *potential_target,
-                            },
-                        )
-                        .expect("cannont insert damage");
-                    return;
+pub fn player_input(gs: &mut State, key: Option<VirtualKeyCode>) -> RunState {
+    match key {
+        None => RunState::AwaitingInput, // Nothing happened
+        Some(VirtualKeyCode::R) => RunState::RevealMap,
+        Some(VirtualKeyCode::Escape) => RunState::SaveGame,
+        Some(VirtualKeyCode::I) => RunState::ShowInventory,
+        Some(VirtualKeyCode::D) => RunState::ShowDropItem,
+        Some(VirtualKeyCode::F) => {
+            let player = *gs.ecs.fetch::<Entity>();
+            let weapon = gs.ecs.system_data::<ActionResolver>().ranged_weapon(player);
+            match weapon {
+                Some(weapon) => start_targeting(&mut gs.ecs, weapon),
+                None => {
+                    gs.ecs
+                        .fetch_mut::<GameLog>()
+                        .entries
+                        .push("You have nothing to shoot with.".to_string());
+                    RunState::AwaitingInput
                 }
             }
         }
+        Some(key) => match key_action(key) {
+            Some(action) => player_action(&mut gs.ecs, action),
+            None => RunState::AwaitingInput, // Do nothing
+        },
+    }
+}
+
+// While an inventory is open a letter picks the item listed under it,
+// `state` says whether to use or drop it. Using gear wears it, a
+// throwable is aimed and anything else is drunk. Any other key closes it.
+pub fn inventory_input(gs: &mut State, key: Option<VirtualKeyCode>, state: RunState) -> RunState {
+    let Some(key) = key else { return state };
+    let player = *gs.ecs.fetch::<Entity>();
+    let carried = backpack_of(&gs.ecs, player);
+    let choice = rltk::letter_to_option(key);
+    let Some(&item) = usize::try_from(choice).ok().and_then(|i| carried.get(i)) else {
+        return RunState::AwaitingInput;
+    };
+    let wearable = gs.ecs.read_storage::<Equippable>().contains(item);
+    let throwable = gs.ecs.read_storage::<Throwable>().contains(item);
+    let action = match state {
+        RunState::ShowDropItem => Action::Drop(item),
+        _ if throwable => return start_targeting(&mut gs.ecs, item),
+        _ if wearable => Action::Equip(item),
+        _ => Action::Drink(item),
+    };
+    player_action(&mut gs.ecs, action)
+}
 
-        // check if the new position is walkable
-        if !map.tiles[dest_idx].is_blocker() {
-            // update the player position
-            pos.point.x = x;
-            pos.point.y = y;
-            // Update the global player position cache
-            point.x = x;
-            point.y = y;
-            viewshed.dirty = true;
+// Aims `with`, unless there is nobody in reach to aim at
+fn start_targeting(ecs: &mut World, with: Entity) -> RunState {
+    let range = ecs.read_storage::<Ranged>().get(with).map_or(0, |r| r.range);
+    if targets(ecs, range).is_empty() {
+        ecs.fetch_mut::<GameLog>()
+            .entries
+            .push("There is nothing in range to shoot at.".to_string());
+        return RunState::AwaitingInput;
+    }
+    ecs.insert(Targeting {
+        with,
+        range,
+        current: 0,
+    });
+    RunState::ShowTargeting
+}
+
+// Tab moves on to the next target, F or enter shoots the one picked and
+// a number shoots the target labelled with it. Any other key stops aiming.
+pub fn targeting_input(gs: &mut State, key: Option<VirtualKeyCode>) -> RunState {
+    let Some(key) = key else {
+        return RunState::ShowTargeting;
+    };
+    let (with, range, current) = {
+        let targeting = gs.ecs.fetch::<Targeting>();
+        (targeting.with, targeting.range, targeting.current)
+    };
+    let found = targets(&gs.ecs, range);
+    let pick = match key {
+        VirtualKeyCode::Tab => {
+            gs.ecs.fetch_mut::<Targeting>().current = (current + 1) % found.len().max(1);
+            return RunState::ShowTargeting;
         }
+        VirtualKeyCode::F | VirtualKeyCode::Return => Some(current),
+        _ => key_target(key),
+    };
+    gs.ecs.remove::<Targeting>();
+    match pick.and_then(|n| found.get(n)) {
+        Some(&(target, _)) => player_action(&mut gs.ecs, Action::Shoot { with, target }),
+        None => RunState::AwaitingInput,
     }
 }
 
-pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
-    // Player movement
-    match ctx.key {
-        None => return RunState::AwaitingInput, // Nothing happened
-        Some(key) => match key {
-            VirtualKeyCode::H => try_move_player(-1, 0, &mut gs.ecs),
-            VirtualKeyCode::L => try_move_player(1, 0, &mut gs.ecs),
-            VirtualKeyCode::K => try_move_player(0, -1, &mut gs.ecs),
-            VirtualKeyCode::J => try_move_player(0, 1, &mut gs.ecs),
-            VirtualKeyCode::U => try_move_player(1, -1, &mut gs.ecs),
-            VirtualKeyCode::Y => try_move_player(-1, -1, &mut gs.ecs),
-            VirtualKeyCode::M => try_move_player(1, 1, &mut gs.ecs),
-            VirtualKeyCode::N => try_move_player(-1, 1, &mut gs.ecs),
-            VirtualKeyCode::R => return RunState::RevealMap,
-            _ => return RunState::AwaitingInput, // Do nothing
-        },
+// How much max hp a level up picked for toughness adds
+pub const LEVEL_UP_HP: i32 = 10;
+
+// A raises max hp, B power and C defense. Nothing else closes the choice,
+// the level is owed. What xp is left may pay for another level at once.
+pub fn level_up_input(gs: &mut State, key: Option<VirtualKeyCode>) -> RunState {
+    let player = *gs.ecs.fetch::<Entity>();
+    let raised = {
+        let mut combat_stats = gs.ecs.write_storage::<CombatStats>();
+        let Some(stats) = combat_stats.get_mut(player) else {
+            return RunState::AwaitingInput;
+        };
+        match key {
+            Some(VirtualKeyCode::A) => {
+                stats.max_hp += LEVEL_UP_HP;
+                stats.hp += LEVEL_UP_HP;
+                "toughness"
+            }
+            Some(VirtualKeyCode::B) => {
+                stats.power += 1;
+                "strength"
+            }
+            Some(VirtualKeyCode::C) => {
+                stats.defense += 1;
+                "guard"
+            }
+            _ => return RunState::LevelUp,
+        }
+    };
+    let again = {
+        let mut experience = gs.ecs.write_storage::<Experience>();
+        let Some(exp) = experience.get_mut(player) else {
+            return RunState::AwaitingInput;
+        };
+        exp.xp -= exp.xp_to_next();
+        exp.level += 1;
+        gs.ecs
+            .fetch_mut::<GameLog>()
+            .entries
+            .push(format!("You reach level {}, your {} grows.", exp.level, raised));
+        exp.can_level_up()
+    };
+    if again {
+        RunState::LevelUp
+    } else if gs.is_players_turn() {
+        RunState::AwaitingInput
+    } else {
+        RunState::Ticking
     }
-    RunState::PlayerTurn
 }
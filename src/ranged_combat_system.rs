@@ -0,0 +1,108 @@
+use specs::prelude::*;
+
+use crate::{
+    components::{
+        CombatStats, DefenseBonus, Equipped, GainsEffect, InBackpack, Inflicts, MeleePowerBonus,
+        Name, Position, Ranged, SufferDamage, Throwable, WantsToShoot,
+    },
+    gamelog::GameLog,
+    inventory_system::{item_name, verb, who},
+    melee_combat_system::effective_stats,
+};
+
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, WantsToShoot>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Ranged>,
+        ReadStorage<'a, Throwable>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, MeleePowerBonus>,
+        ReadStorage<'a, DefenseBonus>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Inflicts>,
+        WriteStorage<'a, GainsEffect>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player,
+            mut gamelog,
+            mut wants_shoot,
+            mut inflict_damage,
+            combat_stats,
+            names,
+            ranged,
+            throwable,
+            equipped,
+            power_bonus,
+            defense_bonus,
+            mut positions,
+            mut backpack,
+            inflicts,
+            mut gains_effect,
+        ) = data;
+
+        for (shooter, shot) in (&entities, &wants_shoot).join() {
+            let (Some(weapon), Some(target_stats)) =
+                (ranged.get(shot.with), combat_stats.get(shot.target))
+            else {
+                continue;
+            };
+            if target_stats.hp <= 0 {
+                continue;
+            }
+            let (_, defense) = effective_stats(
+                shot.target,
+                target_stats,
+                &equipped,
+                &power_bonus,
+                &defense_bonus,
+            );
+            let damage = i32::max(0, weapon.damage - defense);
+
+            let thrown = throwable.contains(shot.with);
+            let actor = who(shooter, *player, &names);
+            let target = who(shot.target, *player, &names);
+            let how = if thrown { "throw" } else { "fire" };
+            let mut line = format!(
+                "{} {} the {} at {}",
+                actor,
+                verb(&actor, how),
+                item_name(shot.with, &names),
+                if target == "You" { "you" } else { &target },
+            );
+            if damage == 0 {
+                line.push('.');
+            } else {
+                line.push_str(&format!(" for {} hit points.", damage));
+                SufferDamage::new_damage(&mut inflict_damage, shot.target, damage, Some(shooter));
+            }
+            gamelog.entries.push(line);
+            // Shots always land, even the harmless ones
+            if let Some(inflicts) = inflicts.get(shot.with) {
+                GainsEffect::new_effect(&mut gains_effect, shot.target, inflicts.inflicted_by(Some(shooter)));
+            }
+
+            // Whatever was thrown can be picked up where it hit
+            if thrown {
+                if let Some(at) = positions.get(shot.target).map(|p| p.point) {
+                    backpack.remove(shot.with);
+                    positions
+                        .insert(shot.with, Position { point: at })
+                        .expect("Unable to land the thrown item");
+                }
+            }
+        }
+        wants_shoot.clear();
+    }
+}
@@ -0,0 +1,711 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::RGB;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    components::{
+        CombatStats, DefenseBonus, Effect, EquipmentSlot, Equippable, Inflicts,
+        MeleePowerBonus, Potion, Ranged, Throwable,
+    },
+    item::ItemSpec,
+    monster::{MonsterSpec, SpawnerSpec},
+    spawn_table::{PerRoom, SpawnEntry, SpawnTable},
+};
+
+// Read at startup, so monsters can be added or tuned without rebuilding
+pub const RAWS_DIR: &str = "./raws";
+
+// Each file is a JSON list of entries like
+//
+//   { "name": "Repressionist", "glyph": "r", "fg": "red", "bg": "black",
+//     "stats": { "hp": 16, "defense": 1, "power": 4 }, "speed": 100 }
+//
+// `bg` may be left out for black. Colours are one of the names in
+// named_colour or "#rrggbb". Spawners also have a
+// "spawn": { "every": 10, "max": 5, "monster": { ... } } with the monster
+// written out in full. Items in items.json have no stats or speed, a
+// potion has "potion": { "heal": 8 } and gear has
+// "equip": { "slot": "melee", "power": 2, "defense": 0 }, where the slot
+// is melee, shield, head, body or ranged and either bonus may be left
+// out. Gear for the ranged slot also has "shoot": { "range": 6,
+// "damage": 4 }, things to throw have "throw" with the same fields.
+// Monsters may start out wearing "equipment": ["Dagger", ...], named
+// from items.json.
+//
+// Monsters, potions and anything shot or thrown may also have
+// "inflicts": { "effect": "poison", "turns": 5, "damage": 2 }, where the
+// effect is poison, confusion, sleep or haste and only poison has damage.
+// A potion that inflicts something may heal 0.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawMonster {
+    name: String,
+    glyph: char,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    stats: RawStats,
+    speed: i32,
+    #[serde(default)]
+    equipment: Vec<String>,
+    inflicts: Option<RawInflicts>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawStats {
+    hp: i32,
+    defense: i32,
+    power: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawSpawner {
+    name: String,
+    glyph: char,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    stats: RawStats,
+    speed: i32,
+    spawn: RawSpawn,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawSpawn {
+    every: i32,
+    max: i32,
+    monster: RawMonster,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawItem {
+    name: String,
+    glyph: char,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    potion: Option<RawPotion>,
+    equip: Option<RawEquip>,
+    shoot: Option<RawRanged>,
+    throw: Option<RawRanged>,
+    inflicts: Option<RawInflicts>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum RawEffect {
+    Poison,
+    Confusion,
+    Sleep,
+    Haste,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawInflicts {
+    effect: RawEffect,
+    turns: i32,
+    #[serde(default)]
+    damage: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawRanged {
+    range: i32,
+    damage: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawPotion {
+    heal: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawEquip {
+    slot: EquipmentSlot,
+    #[serde(default)]
+    power: i32,
+    #[serde(default)]
+    defense: i32,
+}
+
+// spawn_table.json decides what turns up where. Entries name a monster or
+// spawner or item from the other files, `max_depth` may be left out.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawSpawnTable {
+    per_room: RawPerRoom,
+    monsters: Vec<RawSpawnEntry>,
+    spawners: Vec<RawSpawnEntry>,
+    items: Vec<RawSpawnEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawPerRoom {
+    monsters: PerRoom,
+    spawners: PerRoom,
+    items: PerRoom,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawSpawnEntry {
+    name: String,
+    weight: i32,
+    min_depth: i32,
+    max_depth: Option<i32>,
+}
+
+fn default_bg() -> String {
+    "black".to_string()
+}
+
+// Everything the raws define, kept as a resource for building levels
+#[derive(Clone, Debug)]
+pub struct Raws {
+    pub monsters: Vec<MonsterSpec>,
+    pub spawners: Vec<SpawnerSpec>,
+    pub items: Vec<ItemSpec>,
+    pub monster_table: SpawnTable<MonsterSpec>,
+    pub spawner_table: SpawnTable<SpawnerSpec>,
+    pub item_table: SpawnTable<ItemSpec>,
+    pub monsters_per_room: PerRoom,
+    pub spawners_per_room: PerRoom,
+    pub items_per_room: PerRoom,
+}
+
+#[derive(Debug)]
+pub enum RawsError {
+    Io(PathBuf, std::io::Error),
+    Format(PathBuf, serde_json::Error),
+    BadEntry {
+        path: PathBuf,
+        name: String,
+        problem: String,
+    },
+    Empty(PathBuf),
+}
+
+impl fmt::Display for RawsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawsError::Io(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            RawsError::Format(path, e) => write!(f, "{} is malformed: {}", path.display(), e),
+            RawsError::BadEntry {
+                path,
+                name,
+                problem,
+            } => write!(f, "{}: {:?} {}", path.display(), name, problem),
+            RawsError::Empty(path) => write!(f, "{} has no entries", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for RawsError {}
+
+pub fn load_raws<P: AsRef<Path>>(dir: P) -> Result<Raws, RawsError> {
+    let read = |file: &str| {
+        let path = dir.as_ref().join(file);
+        fs::read_to_string(&path)
+            .map(|data| (data, path.clone()))
+            .map_err(|e| RawsError::Io(path, e))
+    };
+    let (monsters, monsters_path) = read("monsters.json")?;
+    let (spawners, spawners_path) = read("spawners.json")?;
+    let (items, items_path) = read("items.json")?;
+    let (table, table_path) = read("spawn_table.json")?;
+    // Monsters wear items, so those come first
+    let items = parse_items(&items_path, &items)?;
+    parse_spawn_table(
+        &table_path,
+        &table,
+        parse_monsters(&monsters_path, &monsters, &items)?,
+        parse_spawners(&spawners_path, &spawners, &items)?,
+        items,
+    )
+}
+
+pub fn parse_spawn_table(
+    path: &Path,
+    data: &str,
+    monsters: Vec<MonsterSpec>,
+    spawners: Vec<SpawnerSpec>,
+    items: Vec<ItemSpec>,
+) -> Result<Raws, RawsError> {
+    let raw: RawSpawnTable =
+        serde_json::from_str(data).map_err(|e| RawsError::Format(path.to_path_buf(), e))?;
+    let per_room = [
+        ("monsters", raw.per_room.monsters),
+        ("spawners", raw.per_room.spawners),
+        ("items", raw.per_room.items),
+    ];
+    for (name, per_room) in per_room {
+        if per_room.min < 0 || per_room.max < per_room.min {
+            return Err(RawsError::BadEntry {
+                path: path.to_path_buf(),
+                name: format!("per_room.{}", name),
+                problem: format!("wants {} to {} per room", per_room.min, per_room.max),
+            });
+        }
+    }
+    Ok(Raws {
+        monster_table: spawn_table(path, raw.monsters, &monsters, |m| &m.name)?,
+        spawner_table: spawn_table(path, raw.spawners, &spawners, |s| &s.name)?,
+        item_table: spawn_table(path, raw.items, &items, |i| &i.name)?,
+        monsters_per_room: raw.per_room.monsters,
+        spawners_per_room: raw.per_room.spawners,
+        items_per_room: raw.per_room.items,
+        monsters,
+        spawners,
+        items,
+    })
+}
+
+fn spawn_table<T: Clone>(
+    path: &Path,
+    entries: Vec<RawSpawnEntry>,
+    specs: &[T],
+    name_of: impl Fn(&T) -> &String,
+) -> Result<SpawnTable<T>, RawsError> {
+    let entries = entries
+        .into_iter()
+        .map(|raw| {
+            let bad = |problem: String| RawsError::BadEntry {
+                path: path.to_path_buf(),
+                name: raw.name.clone(),
+                problem,
+            };
+            let spec = specs
+                .iter()
+                .find(|s| *name_of(s) == raw.name)
+                .ok_or_else(|| bad("isn't defined in the other raws".to_string()))?;
+            if raw.weight < 1 {
+                return Err(bad(format!("has weight {}, it must be at least 1", raw.weight)));
+            }
+            if raw.min_depth < 1 || raw.max_depth.is_some_and(|max| max < raw.min_depth) {
+                return Err(bad(format!(
+                    "has depths {} to {:?}, which is no depth at all",
+                    raw.min_depth, raw.max_depth
+                )));
+            }
+            Ok(SpawnEntry {
+                spec: spec.clone(),
+                weight: raw.weight,
+                min_depth: raw.min_depth,
+                max_depth: raw.max_depth,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(SpawnTable { entries })
+}
+
+pub fn parse_monsters(
+    path: &Path,
+    data: &str,
+    items: &[ItemSpec],
+) -> Result<Vec<MonsterSpec>, RawsError> {
+    parse_entries::<RawMonster>(path, data)?
+        .into_iter()
+        .map(|raw| monster_spec(path, raw, items))
+        .collect()
+}
+
+pub fn parse_spawners(
+    path: &Path,
+    data: &str,
+    items: &[ItemSpec],
+) -> Result<Vec<SpawnerSpec>, RawsError> {
+    parse_entries::<RawSpawner>(path, data)?
+        .into_iter()
+        .map(|raw| spawner_spec(path, raw, items))
+        .collect()
+}
+
+pub fn parse_items(path: &Path, data: &str) -> Result<Vec<ItemSpec>, RawsError> {
+    parse_entries::<RawItem>(path, data)?
+        .into_iter()
+        .map(|raw| item_spec(path, raw))
+        .collect()
+}
+
+fn parse_entries<T: DeserializeOwned>(path: &Path, data: &str) -> Result<Vec<T>, RawsError> {
+    let entries: Vec<T> =
+        serde_json::from_str(data).map_err(|e| RawsError::Format(path.to_path_buf(), e))?;
+    if entries.is_empty() {
+        return Err(RawsError::Empty(path.to_path_buf()));
+    }
+    Ok(entries)
+}
+
+fn monster_spec(path: &Path, raw: RawMonster, items: &[ItemSpec]) -> Result<MonsterSpec, RawsError> {
+    let bad = |problem: String| RawsError::BadEntry {
+        path: path.to_path_buf(),
+        name: raw.name.clone(),
+        problem,
+    };
+    if raw.speed < 0 {
+        return Err(bad(format!("has a negative speed of {}", raw.speed)));
+    }
+    Ok(MonsterSpec {
+        glyph: glyph(raw.glyph).map_err(bad)?,
+        fg: colour(&raw.fg).map_err(bad)?,
+        bg: colour(&raw.bg).map_err(bad)?,
+        combat_stats: Some(stats(&raw.stats).map_err(bad)?),
+        point: rltk::Point::new(0, 0),
+        speed: raw.speed,
+        equipment: equipment(&raw.equipment, items).map_err(bad)?,
+        inflicts: inflicts(raw.inflicts.as_ref()).map_err(bad)?,
+        name: raw.name,
+    })
+}
+
+fn spawner_spec(path: &Path, raw: RawSpawner, items: &[ItemSpec]) -> Result<SpawnerSpec, RawsError> {
+    let bad = |problem: String| RawsError::BadEntry {
+        path: path.to_path_buf(),
+        name: raw.name.clone(),
+        problem,
+    };
+    if raw.speed < 0 {
+        return Err(bad(format!("has a negative speed of {}", raw.speed)));
+    }
+    if raw.spawn.every < 1 {
+        return Err(bad(format!("spawns every {} turns, it must be at least 1", raw.spawn.every)));
+    }
+    if raw.spawn.max < 0 {
+        return Err(bad(format!("spawns at most {} monsters", raw.spawn.max)));
+    }
+    Ok(SpawnerSpec {
+        glyph: glyph(raw.glyph).map_err(bad)?,
+        fg: colour(&raw.fg).map_err(bad)?,
+        bg: colour(&raw.bg).map_err(bad)?,
+        combat_stats: stats(&raw.stats).map_err(bad)?,
+        point: rltk::Point::new(0, 0),
+        spawn_per: raw.spawn.every,
+        spawn_max: raw.spawn.max,
+        speed: raw.speed,
+        spawn_spec: monster_spec(path, raw.spawn.monster, items)?,
+        name: raw.name,
+    })
+}
+
+fn item_spec(path: &Path, raw: RawItem) -> Result<ItemSpec, RawsError> {
+    let bad = |problem: String| RawsError::BadEntry {
+        path: path.to_path_buf(),
+        name: raw.name.clone(),
+        problem,
+    };
+    let uses = [raw.potion.is_some(), raw.equip.is_some(), raw.throw.is_some()];
+    if uses.iter().filter(|u| **u).count() > 1 {
+        return Err(bad("can only be one of a potion, gear or a throwable".to_string()));
+    }
+    let ranged_slot = raw.equip.as_ref().is_some_and(|e| e.slot == EquipmentSlot::Ranged);
+    if ranged_slot != raw.shoot.is_some() {
+        return Err(bad("needs both a ranged slot and \"shoot\", or neither".to_string()));
+    }
+    let ranged = raw.shoot.as_ref().or(raw.throw.as_ref());
+    if ranged.is_some_and(|r| r.range < 1 || r.damage < 0) {
+        return Err(bad("needs a range of at least 1 and no negative damage".to_string()));
+    }
+    if raw.inflicts.is_some() && raw.potion.is_none() && ranged.is_none() {
+        return Err(bad("inflicts an effect but is neither drunk nor shot".to_string()));
+    }
+    let potion = match &raw.potion {
+        Some(potion) if potion.heal < 0 || (potion.heal == 0 && raw.inflicts.is_none()) => {
+            return Err(bad(format!("heals {} hp, it must heal at least 1", potion.heal)))
+        }
+        Some(potion) => Some(Potion {
+            heal_amount: potion.heal,
+        }),
+        None => None,
+    };
+    let equip = raw.equip.as_ref();
+    if equip.is_some_and(|e| e.power < 0 || e.defense < 0) {
+        return Err(bad("has a negative power or defense bonus".to_string()));
+    }
+    Ok(ItemSpec {
+        glyph: glyph(raw.glyph).map_err(bad)?,
+        fg: colour(&raw.fg).map_err(bad)?,
+        bg: colour(&raw.bg).map_err(bad)?,
+        position: None,
+        potion,
+        equippable: equip.map(|e| Equippable { slot: e.slot }),
+        power_bonus: equip
+            .filter(|e| e.power > 0)
+            .map(|e| MeleePowerBonus { power: e.power }),
+        defense_bonus: equip
+            .filter(|e| e.defense > 0)
+            .map(|e| DefenseBonus { defense: e.defense }),
+        ranged: ranged.map(|r| Ranged {
+            range: r.range,
+            damage: r.damage,
+        }),
+        throwable: raw.throw.as_ref().map(|_| Throwable {}),
+        inflicts: inflicts(raw.inflicts.as_ref()).map_err(bad)?,
+        name: raw.name,
+    })
+}
+
+// The gear a monster starts out wearing, at most one item per slot
+fn equipment(names: &[String], items: &[ItemSpec]) -> Result<Vec<ItemSpec>, String> {
+    let mut gear: Vec<ItemSpec> = Vec::new();
+    for name in names {
+        let item = items
+            .iter()
+            .find(|i| i.name == *name)
+            .ok_or_else(|| format!("wears {:?}, which isn't in items.json", name))?;
+        let Some(slot) = item.equippable.as_ref().map(|e| e.slot) else {
+            return Err(format!("wears {:?}, which can't be worn", name));
+        };
+        if gear.iter().any(|g| g.equippable.as_ref().is_some_and(|e| e.slot == slot)) {
+            return Err(format!("wears two items in the {:?} slot", slot));
+        }
+        gear.push(item.clone());
+    }
+    Ok(gear)
+}
+
+fn inflicts(raw: Option<&RawInflicts>) -> Result<Option<Inflicts>, String> {
+    let Some(raw) = raw else { return Ok(None) };
+    if raw.turns < 1 {
+        return Err(format!("inflicts an effect for {} turns, it must last at least 1", raw.turns));
+    }
+    let effect = match raw.effect {
+        RawEffect::Poison if raw.damage < 1 => {
+            return Err(format!("poisons for {} damage, it must do at least 1", raw.damage))
+        }
+        RawEffect::Poison => Effect::Poison { damage: raw.damage },
+        _ if raw.damage != 0 => return Err("has damage on an effect other than poison".to_string()),
+        RawEffect::Confusion => Effect::Confusion,
+        RawEffect::Sleep => Effect::Sleep,
+        RawEffect::Haste => Effect::Haste,
+    };
+    Ok(Some(Inflicts {
+        effect,
+        turns: raw.turns,
+    }))
+}
+
+fn glyph(c: char) -> Result<rltk::FontCharType, String> {
+    match rltk::to_cp437(c) {
+        0 => Err(format!("has glyph {:?}, which the font doesn't have", c)),
+        g => Ok(g),
+    }
+}
+
+fn stats(raw: &RawStats) -> Result<CombatStats, String> {
+    if raw.hp < 1 {
+        return Err(format!("has {} hp, it needs at least 1", raw.hp));
+    }
+    if raw.defense < 0 || raw.power < 0 {
+        return Err("has negative defense or power".to_string());
+    }
+    Ok(CombatStats {
+        max_hp: raw.hp,
+        hp: raw.hp,
+        defense: raw.defense,
+        power: raw.power,
+    })
+}
+
+fn colour(name: &str) -> Result<RGB, String> {
+    if name.starts_with('#') {
+        return RGB::from_hex(name).map_err(|_| format!("has colour {:?}, which isn't #rrggbb", name));
+    }
+    named_colour(&name.to_lowercase())
+        .map(RGB::named)
+        .ok_or_else(|| format!("has colour {:?}, which isn't a colour name or #rrggbb", name))
+}
+
+fn named_colour(name: &str) -> Option<(u8, u8, u8)> {
+    Some(match name {
+        "black" => rltk::BLACK,
+        "white" => rltk::WHITE,
+        "grey" | "gray" => rltk::GREY,
+        "dark_grey" | "dark_gray" => rltk::DARK_GREY,
+        "red" => rltk::RED,
+        "dark_red" => rltk::DARK_RED,
+        "orange" => rltk::ORANGE,
+        "yellow" => rltk::YELLOW,
+        "gold" => rltk::GOLD,
+        "green" => rltk::GREEN,
+        "dark_green" => rltk::DARK_GREEN,
+        "cyan" => rltk::CYAN,
+        "blue" => rltk::BLUE,
+        "navy" => rltk::NAVY,
+        "purple" => rltk::PURPLE,
+        "magenta" => rltk::MAGENTA,
+        "pink" => rltk::PINK,
+        "brown" => rltk::BROWN1,
+        _ => return None,
+    })
+}
+
+// A small fixed set of raws for tests, so editing raws/*.json for balance
+// doesn't move them. Covers every slot, something to throw and a spawner.
+#[cfg(test)]
+pub fn test_raws() -> Raws {
+    let items = serde_json::json!([
+        { "name": "Healing Potion", "glyph": "!", "fg": "magenta", "potion": { "heal": 8 } },
+        { "name": "Dagger", "glyph": "/", "fg": "cyan", "equip": { "slot": "melee", "power": 2 } },
+        { "name": "Shield", "glyph": "(", "fg": "cyan", "equip": { "slot": "shield", "defense": 1 } },
+        { "name": "Helmet", "glyph": "^", "fg": "cyan", "equip": { "slot": "head", "defense": 1 } },
+        { "name": "Leather Armour", "glyph": "[", "fg": "brown", "equip": { "slot": "body", "defense": 2 } },
+        {
+            "name": "Sling", "glyph": "}", "fg": "brown",
+            "equip": { "slot": "ranged" },
+            "shoot": { "range": 6, "damage": 4 },
+        },
+        { "name": "Throwing Knife", "glyph": "|", "fg": "cyan", "throw": { "range": 5, "damage": 5 } },
+        {
+            "name": "Poison Dart", "glyph": "-", "fg": "green",
+            "throw": { "range": 6, "damage": 1 },
+            "inflicts": { "effect": "poison", "turns": 5, "damage": 2 },
+        },
+    ]);
+    let monsters = serde_json::json!([
+        { "name": "Repressionist", "glyph": "r", "fg": "red", "stats": { "hp": 16, "defense": 1, "power": 4 }, "speed": 100 },
+        {
+            "name": "Scold", "glyph": "s", "fg": "orange",
+            "stats": { "hp": 10, "defense": 0, "power": 2 },
+            "speed": 100,
+            "equipment": ["Sling"],
+        },
+    ]);
+    let spawners = serde_json::json!([{
+        "name": "Cow Spawner", "glyph": "C", "fg": "red",
+        "stats": { "hp": 2, "defense": 1, "power": 1 },
+        "speed": 100,
+        "spawn": {
+            "every": 10,
+            "max": 5,
+            "monster": { "name": "Cow Spawnling", "glyph": "c", "fg": "red", "stats": { "hp": 2, "defense": 1, "power": 1 }, "speed": 50 },
+        },
+    }]);
+    let table = serde_json::json!({
+        "per_room": {
+            "monsters": { "min": 0, "max": 2 },
+            "spawners": { "min": 0, "max": 1 },
+            "items": { "min": 0, "max": 1 },
+        },
+        "monsters": [
+            { "name": "Repressionist", "weight": 10, "min_depth": 1 },
+            { "name": "Scold", "weight": 4, "min_depth": 2 },
+        ],
+        "spawners": [{ "name": "Cow Spawner", "weight": 4, "min_depth": 1 }],
+        "items": [
+            { "name": "Healing Potion", "weight": 8, "min_depth": 1 },
+            { "name": "Dagger", "weight": 3, "min_depth": 1 },
+            { "name": "Shield", "weight": 3, "min_depth": 1 },
+            { "name": "Helmet", "weight": 2, "min_depth": 1 },
+            { "name": "Leather Armour", "weight": 2, "min_depth": 1 },
+            { "name": "Sling", "weight": 2, "min_depth": 1 },
+            { "name": "Throwing Knife", "weight": 3, "min_depth": 1 },
+            { "name": "Poison Dart", "weight": 2, "min_depth": 1 },
+        ],
+    });
+    let items = parse_items(Path::new("items.json"), &items.to_string()).unwrap();
+    parse_spawn_table(
+        Path::new("spawn_table.json"),
+        &table.to_string(),
+        parse_monsters(Path::new("monsters.json"), &monsters.to_string(), &items).unwrap(),
+        parse_spawners(Path::new("spawners.json"), &spawners.to_string(), &items).unwrap(),
+        items,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+fn monster_json(glyph: char, fg: &str, hp: i32, speed: i32) -> String {
+    serde_json::json!([{
+        "name": "Test Monster",
+        "glyph": glyph,
+        "fg": fg,
+        "stats": { "hp": hp, "defense": 0, "power": 1 },
+        "speed": speed,
+    }])
+    .to_string()
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_shipped_raws_stock_every_depth(depth in 1..20i32, seed in any::<u64>()) {
+        let raws = load_raws(RAWS_DIR).unwrap();
+        let mut rng = rltk::RandomNumberGenerator::seeded(seed);
+        prop_assert!(raws.monster_table.roll(&mut rng, depth).is_some());
+        prop_assert!(raws.item_table.roll(&mut rng, depth).is_some());
+    }
+
+    #[test]
+    fn test_bad_entries_are_named(
+        glyph in prop::sample::select(vec!['a', 'Z', '@', '\u{1F600}']),
+        fg in prop::sample::select(vec!["red", "Dark_Green", "#ff8000", "redd", "#12"]),
+        hp in -2..20i32,
+        speed in -50..200i32,
+    ) {
+        let path = Path::new("monsters.json");
+        let result = parse_monsters(path, &monster_json(glyph, fg, hp, speed), &[]);
+        let valid = glyph != '\u{1F600}' && fg != "redd" && fg != "#12" && hp >= 1 && speed >= 0;
+        match result {
+            Ok(specs) => {
+                prop_assert!(valid);
+                prop_assert_eq!(specs.len(), 1);
+            }
+            Err(e) => {
+                prop_assert!(!valid);
+                prop_assert!(e.to_string().contains("Test Monster"), "{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn_table_entries_are_checked(
+        name in prop::sample::select(vec!["Repressionist", "Nobody"]),
+        weight in -1..3i32,
+        min_depth in 0..3i32,
+        max_depth in prop::option::of(0..4i32),
+    ) {
+        let shipped = test_raws();
+        let table = serde_json::json!({
+            "per_room": {
+                "monsters": { "min": 0, "max": 2 },
+                "spawners": { "min": 0, "max": 1 },
+                "items": { "min": 0, "max": 1 },
+            },
+            "monsters": [{ "name": name, "weight": weight, "min_depth": min_depth, "max_depth": max_depth }],
+            "spawners": [],
+            "items": [],
+        });
+        let result = parse_spawn_table(
+            Path::new("spawn_table.json"),
+            &table.to_string(),
+            shipped.monsters,
+            shipped.spawners,
+            shipped.items,
+        );
+        let valid = name == "Repressionist" && weight >= 1 && min_depth >= 1 && max_depth.is_none_or(|m| m >= min_depth);
+        match result {
+            Ok(raws) => {
+                prop_assert!(valid);
+                prop_assert_eq!(raws.monster_table.entries.len(), 1);
+            }
+            Err(e) => {
+                prop_assert!(!valid);
+                prop_assert!(e.to_string().contains(name), "{}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::VirtualKeyCode;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::{gamelog::GameLog, rng::MasterSeed};
+
+// Every new run is recorded here, replacing the last one
+pub const REPLAY_PATH: &str = "./last_run.replay";
+
+pub const REPLAY_VERSION: u32 = 1;
+
+// A replay is a header line followed by one key per line, in the order
+// player_input consumed them. The seed is enough to rebuild everything
+// else, so the keys are all that has to be stored.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct ReplayHeader {
+    version: u32,
+    seed: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub keys: Vec<VirtualKeyCode>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    WrongVersion(u32),
+    NoHeader,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "unable to access the replay: {}", e),
+            ReplayError::Format(e) => write!(f, "the replay is corrupt: {}", e),
+            ReplayError::WrongVersion(v) => write!(
+                f,
+                "the replay is version {}, this game reads version {}",
+                v, REPLAY_VERSION
+            ),
+            ReplayError::NoHeader => write!(f, "the replay is empty"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(e: serde_json::Error) -> Self {
+        ReplayError::Format(e)
+    }
+}
+
+// Each key is written as soon as it is pressed, so a replay survives the
+// game crashing, which is when it is wanted most
+pub struct InputRecorder {
+    file: File,
+}
+
+impl InputRecorder {
+    pub fn create<P: AsRef<Path>>(path: P, seed: u64) -> Result<InputRecorder, ReplayError> {
+        let mut file = File::create(path)?;
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            seed,
+        };
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(InputRecorder { file })
+    }
+
+    pub fn record(&mut self, key: VirtualKeyCode) -> Result<(), ReplayError> {
+        writeln!(self.file, "{}", serde_json::to_string(&key)?)?;
+        Ok(())
+    }
+}
+
+// Records the run from here on. Only new runs are recorded, a continued
+// save doesn't start from its seed so a replay of it would be useless.
+pub fn start_recording(ecs: &mut World) {
+    let seed = ecs.fetch::<MasterSeed>().0;
+    match InputRecorder::create(REPLAY_PATH, seed) {
+        Ok(recorder) => ecs.insert(recorder),
+        Err(e) => ecs
+            .fetch_mut::<GameLog>()
+            .entries
+            .push(format!("Unable to record this run, {}", e)),
+    }
+}
+
+pub fn parse_replay(data: &str) -> Result<Replay, ReplayError> {
+    let mut lines = data.lines().filter(|l| !l.trim().is_empty());
+    let header: ReplayHeader = serde_json::from_str(lines.next().ok_or(ReplayError::NoHeader)?)?;
+    if header.version != REPLAY_VERSION {
+        return Err(ReplayError::WrongVersion(header.version));
+    }
+    let keys = lines
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<VirtualKeyCode>, _>>()?;
+    Ok(Replay {
+        seed: header.seed,
+        keys,
+    })
+}
+
+pub fn load_replay<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+    parse_replay(&fs::read_to_string(path)?)
+}
+
+// Stands in for the keyboard while a replay plays. Space steps one
+// recorded key, enter plays the rest one key per frame and escape quits.
+pub struct ReplayPlayer {
+    pub keys: Vec<VirtualKeyCode>,
+    pub next: usize,
+    pub running: bool,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            keys: replay.keys,
+            next: 0,
+            running: false,
+        }
+    }
+
+    pub fn next_key(&mut self, pressed: Option<VirtualKeyCode>) -> Option<VirtualKeyCode> {
+        match pressed {
+            Some(VirtualKeyCode::Escape) => return Some(VirtualKeyCode::Escape),
+            Some(VirtualKeyCode::Return) => self.running = !self.running,
+            _ => {}
+        }
+        if !self.running && pressed != Some(VirtualKeyCode::Space) {
+            return None;
+        }
+        let key = self.keys.get(self.next).copied();
+        if key.is_some() {
+            self.next += 1;
+        }
+        key
+    }
+}
+
+// The key player_input gets this frame. During a replay the keyboard only
+// drives the ReplayPlayer, otherwise whatever was pressed is recorded.
+// Clicks that stand for a key are passed in as that key, so they replay.
+pub fn next_input(ecs: &mut World, pressed: Option<VirtualKeyCode>) -> Option<VirtualKeyCode> {
+    if let Some(mut player) = ecs.try_fetch_mut::<ReplayPlayer>() {
+        return player.next_key(pressed);
+    }
+    if let (Some(key), Some(mut recorder)) = (pressed, ecs.try_fetch_mut::<InputRecorder>()) {
+        if let Err(e) = recorder.record(key) {
+            eprintln!("Unable to record {:?}: {}", key, e);
+        }
+    }
+    pressed
+}
+
+// Looks for `--replay <file>` in the command line arguments
+pub fn replay_path_from_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<Option<String>, String> {
+    while let Some(arg) = args.next() {
+        if arg == "--replay" {
+            return args.next().map(Some).ok_or_else(|| "--replay needs a file".to_string());
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+fn any_key() -> impl Strategy<Value = VirtualKeyCode> {
+    prop::sample::select(vec![
+        VirtualKeyCode::H,
+        VirtualKeyCode::J,
+        VirtualKeyCode::K,
+        VirtualKeyCode::L,
+        VirtualKeyCode::Y,
+        VirtualKeyCode::U,
+        VirtualKeyCode::N,
+        VirtualKeyCode::M,
+        VirtualKeyCode::Period,
+        VirtualKeyCode::Comma,
+        VirtualKeyCode::Escape,
+        VirtualKeyCode::Q,
+    ])
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_recording_round_trip(seed in any::<u64>(), keys in prop::collection::vec(any_key(), 0..50)) {
+        let path = std::env::temp_dir().join(format!("rouge-{}-{}.replay", std::process::id(), seed));
+        let mut recorder = InputRecorder::create(&path, seed).unwrap();
+        for key in keys.iter() {
+            recorder.record(*key).unwrap();
+        }
+        drop(recorder);
+        let replay = load_replay(&path);
+        fs::remove_file(&path).unwrap();
+        prop_assert_eq!(replay.unwrap(), Replay { seed, keys });
+    }
+
+    #[test]
+    fn test_stepping_plays_every_key_once(keys in prop::collection::vec(any_key(), 0..20)) {
+        let mut player = ReplayPlayer::new(Replay { seed: 0, keys: keys.clone() });
+        prop_assert_eq!(player.next_key(None), None);
+        let mut played = Vec::new();
+        while let Some(key) = player.next_key(Some(VirtualKeyCode::Space)) {
+            played.push(key);
+        }
+        prop_assert_eq!(played, keys);
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_replay_reproduces_the_run(seed in any::<u64>(), keys in prop::collection::vec(crate::state::any_command(), 0..300)) {
+        use crate::components::Position;
+        use crate::state::{test_state, State};
+
+        let path = std::env::temp_dir().join(format!("rouge-run-{}-{}.replay", std::process::id(), seed));
+        let mut recorder = InputRecorder::create(&path, seed).unwrap();
+        let mut original = test_state(seed);
+        for key in keys {
+            if !original.run_until_input().awaits_input() {
+                break;
+            }
+            recorder.record(key).unwrap();
+            original.play_turn(key);
+        }
+        drop(recorder);
+
+        let replay = load_replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut replayed = test_state(replay.seed);
+        replayed.play(replay.keys);
+
+        let player_at = |gs: &State| gs.ecs.read_storage::<Position>().get(*gs.ecs.fetch::<Entity>()).unwrap().point;
+        prop_assert_eq!(*replayed.ecs.fetch::<crate::state::RunState>(), *original.ecs.fetch::<crate::state::RunState>());
+        prop_assert_eq!(&replayed.ecs.fetch::<GameLog>().entries, &original.ecs.fetch::<GameLog>().entries);
+        prop_assert_eq!(player_at(&replayed), player_at(&original));
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+// Every random number in a run comes from one master seed. Each part of
+// the game draws from its own stream, so an extra roll while playing
+// can't change what the next level looks like.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MasterSeed(pub u64);
+
+// Rolls made while the level is being played, e.g. where a spawner puts
+// its children. Building a level uses level_rng instead.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpawnRng(pub RandomNumberGenerator);
+// Where the confused stumble to
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StatusRng(pub RandomNumberGenerator);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Map = 1,
+    Monsters = 2,
+    Spawns = 3,
+    // 4 and 5 were combat and AI streams nothing drew from. The numbers
+    // stay retired so the other streams keep their seeds.
+    Status = 6,
+}
+
+// splitmix64, so neighbouring seeds and streams end up far apart
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn stream_seed(master: u64, stream: Stream, n: u64) -> u64 {
+    mix(mix(mix(master) ^ stream as u64) ^ n)
+}
+
+// A fresh generator for building one level, so the same seed always
+// gives the same level at a given depth however the player got there
+pub fn level_rng(master: u64, stream: Stream, depth: i32) -> RandomNumberGenerator {
+    RandomNumberGenerator::seeded(stream_seed(master, stream, depth as u64))
+}
+
+pub fn insert_rng_streams(ecs: &mut World, master: u64) {
+    ecs.insert(MasterSeed(master));
+    ecs.insert(SpawnRng(RandomNumberGenerator::seeded(stream_seed(master, Stream::Spawns, 0))));
+    ecs.insert(StatusRng(RandomNumberGenerator::seeded(stream_seed(master, Stream::Status, 0))));
+}
+
+// Looks for `--seed <n>` in the command line arguments
+pub fn seed_from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<u64>, String> {
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            let value = args.next().ok_or("--seed needs a value")?;
+            return value
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|e| format!("Invalid seed {:?}: {}", value, e));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+fn build_first_level(seed: u64) -> (Vec<crate::map::TileType>, Vec<(String, rltk::Point)>) {
+    use crate::components::{Name, Position};
+    use crate::map::Map;
+    use crate::state::test_state;
+
+    let gs = test_state(seed);
+    let names = gs.ecs.read_storage::<Name>();
+    let positions = gs.ecs.read_storage::<Position>();
+    let monsters = (&names, &positions)
+        .join()
+        .map(|(n, p)| (n.name.clone(), p.point))
+        .collect();
+    let tiles = gs.ecs.fetch::<Map>().tiles.clone();
+    (tiles, monsters)
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+    #[test]
+    fn test_same_seed_same_level(seed in any::<u64>()) {
+        prop_assert_eq!(build_first_level(seed), build_first_level(seed));
+    }
+
+    #[test]
+    fn test_seed_from_args(seed in any::<u64>()) {
+        let args = vec!["rouge".to_string(), "--seed".to_string(), seed.to_string()];
+        prop_assert_eq!(seed_from_args(args.into_iter()), Ok(Some(seed)));
+        prop_assert_eq!(seed_from_args(vec!["rouge".to_string()].into_iter()), Ok(None));
+        prop_assert!(seed_from_args(vec!["--seed".to_string(), "x".to_string()].into_iter()).is_err());
+    }
+}
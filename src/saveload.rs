@@ -0,0 +1,381 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::Point;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use specs::saveload::{
+    DeserializeComponents, MarkerAllocator, SerializeComponents, SimpleMarker, SimpleMarkerAllocator,
+};
+use std::convert::Infallible;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    components::*,
+    gamelog::GameLog,
+    map::{Map, MapGenConfig},
+    map_index_system::MapIndexingSystem,
+    rng::{MasterSeed, SpawnRng, StatusRng},
+    state::RunState,
+};
+#[cfg(test)]
+use crate::state::test_state;
+
+pub const SAVE_PATH: &str = "./savegame.json";
+
+// Bump this whenever a saved component or resource changes shape, old
+// saves are refused rather than loaded half right
+pub const SAVE_VERSION: u32 = 9;
+
+// Marks the entities that go into the save. Entity ids aren't stable
+// across runs, so references between entities are written as markers.
+pub struct SerializeMe;
+
+// Every registered component, read or written in the same order. The
+// position in the tuple is what identifies a component in the file, so
+// add new ones at the end and bump SAVE_VERSION. specs takes at most 16
+// at once, so items and status effects have tuples of their own.
+macro_rules! saved_components {
+    ($ecs:expr, $storage:ident) => {
+        (
+            $ecs.$storage::<Position>(),
+            $ecs.$storage::<Renderable>(),
+            $ecs.$storage::<Name>(),
+            $ecs.$storage::<Viewshed>(),
+            $ecs.$storage::<Monster>(),
+            $ecs.$storage::<Player>(),
+            $ecs.$storage::<CombatStats>(),
+            $ecs.$storage::<WantsToMelee>(),
+            $ecs.$storage::<SufferDamage>(),
+            $ecs.$storage::<BlocksTile>(),
+            $ecs.$storage::<Spawner>(),
+            $ecs.$storage::<SpawnedBy>(),
+            $ecs.$storage::<Energy>(),
+            $ecs.$storage::<MyTurn>(),
+            $ecs.$storage::<SlainBy>(),
+            $ecs.$storage::<Experience>(),
+        )
+    };
+}
+
+macro_rules! saved_item_components {
+    ($ecs:expr, $storage:ident) => {
+        (
+            $ecs.$storage::<Item>(),
+            $ecs.$storage::<Potion>(),
+            $ecs.$storage::<InBackpack>(),
+            $ecs.$storage::<WantsToPickupItem>(),
+            $ecs.$storage::<WantsToDrinkPotion>(),
+            $ecs.$storage::<WantsToDropItem>(),
+            $ecs.$storage::<Equippable>(),
+            $ecs.$storage::<Equipped>(),
+            $ecs.$storage::<MeleePowerBonus>(),
+            $ecs.$storage::<DefenseBonus>(),
+            $ecs.$storage::<WantsToEquip>(),
+            $ecs.$storage::<Ranged>(),
+            $ecs.$storage::<Throwable>(),
+            $ecs.$storage::<WantsToShoot>(),
+        )
+    };
+}
+
+macro_rules! saved_status_components {
+    ($ecs:expr, $storage:ident) => {
+        (
+            $ecs.$storage::<StatusEffects>(),
+            $ecs.$storage::<GainsEffect>(),
+            $ecs.$storage::<Inflicts>(),
+        )
+    };
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    seed: MasterSeed,
+    spawn_rng: SpawnRng,
+    status_rng: StatusRng,
+    map_gen_config: MapGenConfig,
+    map: Map,
+    log: GameLog,
+    runstate: RunState,
+    entities: serde_json::Value,
+    item_entities: serde_json::Value,
+    status_entities: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    WrongVersion(u32),
+    NoPlayer,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "unable to access the save file: {}", e),
+            SaveError::Format(e) => write!(f, "the save file is corrupt: {}", e),
+            SaveError::WrongVersion(v) => write!(
+                f,
+                "the save file is version {}, this game reads version {}",
+                v, SAVE_VERSION
+            ),
+            SaveError::NoPlayer => write!(f, "the save file has no player in it"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveError::Format(e)
+    }
+}
+
+pub fn save_exists() -> bool {
+    Path::new(SAVE_PATH).exists()
+}
+
+pub fn delete_save() {
+    if save_exists() {
+        fs::remove_file(SAVE_PATH).expect("Unable to delete the save file");
+    }
+}
+
+pub fn save_game(ecs: &mut World) -> Result<(), SaveError> {
+    fs::write(SAVE_PATH, save_to_string(ecs)?)?;
+    Ok(())
+}
+
+pub fn load_game(ecs: &mut World) -> Result<(), SaveError> {
+    load_from_str(ecs, &fs::read_to_string(SAVE_PATH)?)
+}
+
+pub fn save_to_string(ecs: &mut World) -> Result<String, SaveError> {
+    // Entities only get a marker the first time they are saved
+    {
+        let entities = ecs.entities();
+        let mut markers = ecs.write_storage::<SimpleMarker<SerializeMe>>();
+        let mut allocator = ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>();
+        for entity in entities.join() {
+            allocator.mark(entity, &mut markers);
+        }
+    }
+
+    let entities = SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+        &saved_components!(ecs, read_storage),
+        &ecs.entities(),
+        &ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+        serde_json::value::Serializer,
+    )?;
+    let item_entities = SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+        &saved_item_components!(ecs, read_storage),
+        &ecs.entities(),
+        &ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+        serde_json::value::Serializer,
+    )?;
+    let status_entities = SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+        &saved_status_components!(ecs, read_storage),
+        &ecs.entities(),
+        &ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+        serde_json::value::Serializer,
+    )?;
+
+    let save = SaveFile {
+        version: SAVE_VERSION,
+        seed: *ecs.fetch::<MasterSeed>(),
+        spawn_rng: (*ecs.fetch::<SpawnRng>()).clone(),
+        status_rng: (*ecs.fetch::<StatusRng>()).clone(),
+        map_gen_config: *ecs.fetch::<MapGenConfig>(),
+        map: (*ecs.fetch::<Map>()).clone(),
+        log: (*ecs.fetch::<GameLog>()).clone(),
+        runstate: *ecs.fetch::<RunState>(),
+        entities,
+        item_entities,
+        status_entities,
+    };
+    Ok(serde_json::to_string(&save)?)
+}
+
+// Replaces every entity and saved resource in the world with the ones
+// in `data`. A save that can't be read in full is refused before
+// anything in the world is touched.
+pub fn load_from_str(ecs: &mut World, data: &str) -> Result<(), SaveError> {
+    let save: SaveFile = serde_json::from_str(data)?;
+    if save.version != SAVE_VERSION {
+        return Err(SaveError::WrongVersion(save.version));
+    }
+
+    // A trial run on a world of its own, reading the entities can still
+    // fail halfway through and there must be a player to continue as
+    let mut scratch = World::new();
+    register_all_components(&mut scratch);
+    read_entities(&scratch, &save)?;
+    find_player(&scratch)?;
+
+    ecs.delete_all();
+    ecs.maintain();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    read_entities(ecs, &save)?;
+    let (player, start) = find_player(ecs)?;
+
+    let mut map = save.map;
+    map.tile_content = vec![Vec::new(); map.tiles.len()];
+    map.blocked = vec![false; map.tiles.len()];
+    ecs.insert(map);
+    ecs.insert(save.seed);
+    ecs.insert(save.spawn_rng);
+    ecs.insert(save.status_rng);
+    ecs.insert(save.map_gen_config);
+    ecs.insert(save.log);
+    ecs.insert(save.runstate);
+    ecs.insert(player);
+    ecs.insert::<Point>(start);
+
+    let mut mapindex = MapIndexingSystem {};
+    mapindex.run_now(ecs);
+    Ok(())
+}
+
+fn read_entities(ecs: &World, save: &SaveFile) -> Result<(), SaveError> {
+    DeserializeComponents::<Infallible, SimpleMarker<SerializeMe>>::deserialize(
+        &mut saved_components!(ecs, write_storage),
+        &ecs.entities(),
+        &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+        &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+        save.entities.clone(),
+    )?;
+    // Same markers, so these land on the entities made above
+    DeserializeComponents::<Infallible, SimpleMarker<SerializeMe>>::deserialize(
+        &mut saved_item_components!(ecs, write_storage),
+        &ecs.entities(),
+        &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+        &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+        save.item_entities.clone(),
+    )?;
+    DeserializeComponents::<Infallible, SimpleMarker<SerializeMe>>::deserialize(
+        &mut saved_status_components!(ecs, write_storage),
+        &ecs.entities(),
+        &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+        &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+        save.status_entities.clone(),
+    )?;
+    Ok(())
+}
+
+fn find_player(ecs: &World) -> Result<(Entity, Point), SaveError> {
+    let entities = ecs.entities();
+    let players = ecs.read_storage::<Player>();
+    let positions = ecs.read_storage::<Position>();
+    (&entities, &players, &positions)
+        .join()
+        .map(|(e, _, pos)| (e, pos.point))
+        .next()
+        .ok_or(SaveError::NoPlayer)
+}
+
+// Loading over a running game reuses its entity ids in another order,
+// so saves are compared with their entities sorted by marker
+#[cfg(test)]
+fn by_marker(saved: &str) -> serde_json::Value {
+    let mut value: serde_json::Value = serde_json::from_str(saved).unwrap();
+    for list in ["entities", "item_entities", "status_entities"] {
+        value[list]
+            .as_array_mut()
+            .unwrap()
+            .sort_by_key(|e| e["marker"][0].as_u64());
+    }
+    value
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_save_load_round_trip(seed in any::<u64>(), revealed in prop::collection::vec(0..80 * 50usize, 0..100)) {
+        let mut ecs = test_state(seed).ecs;
+        for idx in revealed {
+            ecs.fetch_mut::<Map>().revealed_tiles[idx] = true;
+        }
+        // Point the player and every monster at each other so the
+        // entity references have something to survive
+        let player = *ecs.fetch::<Entity>();
+        let monsters: Vec<Entity> = (&ecs.entities(), &ecs.read_storage::<Monster>())
+            .join()
+            .map(|(e, _)| e)
+            .collect();
+        for m in monsters.iter() {
+            ecs.write_storage::<WantsToMelee>().insert(*m, WantsToMelee { target: player }).unwrap();
+        }
+        if let Some(m) = monsters.first() {
+            ecs.write_storage::<WantsToMelee>().insert(player, WantsToMelee { target: *m }).unwrap();
+            SufferDamage::new_damage(&mut ecs.write_storage::<SufferDamage>(), *m, 3, Some(player));
+            let dose = ActiveEffect { effect: Effect::Poison { damage: 1 }, turns: 3, from: Some(player) };
+            ecs.write_storage::<StatusEffects>().insert(*m, StatusEffects { active: vec![dose] }).unwrap();
+        }
+
+        let saved = save_to_string(&mut ecs).unwrap();
+        let mut loaded = test_state(seed.wrapping_add(1)).ecs;
+        load_from_str(&mut loaded, &saved).unwrap();
+
+        prop_assert_eq!(by_marker(&save_to_string(&mut loaded).unwrap()), by_marker(&saved));
+        prop_assert_eq!(&loaded.fetch::<Map>().revealed_tiles, &ecs.fetch::<Map>().revealed_tiles);
+
+        let player = *loaded.fetch::<Entity>();
+        let names = loaded.read_storage::<Name>();
+        let wants = loaded.read_storage::<WantsToMelee>();
+        prop_assert_eq!(&names.get(player).unwrap().name, "Player");
+        for (e, _) in (&loaded.entities(), &loaded.read_storage::<Monster>()).join() {
+            prop_assert_eq!(wants.get(e).unwrap().target, player);
+        }
+        if let Some(w) = wants.get(player) {
+            prop_assert!(loaded.read_storage::<SufferDamage>().get(w.target).is_some());
+            let statuses = loaded.read_storage::<StatusEffects>();
+            prop_assert_eq!(statuses.get(w.target).unwrap().active[0].from, Some(player));
+        }
+    }
+
+    #[test]
+    fn test_broken_saves_leave_the_world_alone(seed in any::<u64>(), cut in 0.0..1.0f64) {
+        let mut ecs = test_state(seed).ecs;
+        let saved = save_to_string(&mut ecs).unwrap();
+        let truncated = &saved[..(saved.len() as f64 * cut) as usize];
+        let mut misshapen: serde_json::Value = serde_json::from_str(&saved).unwrap();
+        misshapen["item_entities"] = serde_json::json!([{ "marker": [0], "components": "nonsense" }]);
+        let nobody = {
+            let mut other = test_state(seed).ecs;
+            let player = *other.fetch::<Entity>();
+            other.delete_entity(player).unwrap();
+            save_to_string(&mut other).unwrap()
+        };
+
+        let mut loaded = test_state(seed).ecs;
+        let before = by_marker(&save_to_string(&mut loaded).unwrap());
+        prop_assert!(matches!(load_from_str(&mut loaded, truncated), Err(SaveError::Format(_))));
+        prop_assert!(matches!(load_from_str(&mut loaded, &misshapen.to_string()), Err(SaveError::Format(_))));
+        prop_assert!(matches!(load_from_str(&mut loaded, &nobody), Err(SaveError::NoPlayer)));
+
+        let player = *loaded.fetch::<Entity>();
+        prop_assert!(loaded.is_alive(player));
+        prop_assert_eq!(by_marker(&save_to_string(&mut loaded).unwrap()), before);
+    }
+
+    #[test]
+    fn test_other_versions_are_refused(version in any::<u32>()) {
+        prop_assume!(version != SAVE_VERSION);
+        let mut ecs = test_state(1).ecs;
+        let saved = save_to_string(&mut ecs).unwrap();
+        let old = saved.replacen(&format!("\"version\":{}", SAVE_VERSION), &format!("\"version\":{}", version), 1);
+        prop_assert!(matches!(load_from_str(&mut ecs, &old), Err(SaveError::WrongVersion(v)) if v == version));
+    }
+}
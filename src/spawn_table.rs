@@ -0,0 +1,104 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::RandomNumberGenerator;
+use serde::Deserialize;
+
+#[derive(Clone, Debug)]
+pub struct SpawnEntry<T> {
+    pub spec: T,
+    pub weight: i32,
+    pub min_depth: i32,
+    // None keeps it around however deep the player goes
+    pub max_depth: Option<i32>,
+}
+
+impl<T> SpawnEntry<T> {
+    pub fn allowed_at(&self, depth: i32) -> bool {
+        depth >= self.min_depth && self.max_depth.is_none_or(|max| depth <= max)
+    }
+}
+
+// What can turn up at each depth and how often. An entry with twice the
+// weight of another is picked twice as often, as long as both are allowed.
+#[derive(Clone, Debug)]
+pub struct SpawnTable<T> {
+    pub entries: Vec<SpawnEntry<T>>,
+}
+
+impl<T: Clone> SpawnTable<T> {
+    // None when nothing in the table is allowed this deep
+    pub fn roll(&self, rng: &mut RandomNumberGenerator, depth: i32) -> Option<T> {
+        let allowed = || self.entries.iter().filter(|e| e.allowed_at(depth));
+        let total: i32 = allowed().map(|e| e.weight).sum();
+        if total <= 0 {
+            return None;
+        }
+        let mut roll = rng.range(0, total);
+        for entry in allowed() {
+            if roll < entry.weight {
+                return Some(entry.spec.clone());
+            }
+            roll -= entry.weight;
+        }
+        None
+    }
+}
+
+// How many of something each room gets, both ends included
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PerRoom {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl PerRoom {
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> i32 {
+        rng.range(self.min, self.max + 1)
+    }
+}
+
+#[cfg(test)]
+fn any_entry() -> impl Strategy<Value = (i32, i32, Option<i32>)> {
+    (1..5i32, 1..6i32, prop::option::of(0..4i32))
+        .prop_map(|(weight, min, extra)| (weight, min, extra.map(|e| min + e)))
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_rolls_cover_what_is_allowed(
+        seed in any::<u64>(),
+        depth in 1..10i32,
+        entries in prop::collection::vec(any_entry(), 1..5),
+    ) {
+        let table = SpawnTable {
+            entries: entries
+                .iter()
+                .enumerate()
+                .map(|(i, (weight, min_depth, max_depth))| SpawnEntry {
+                    spec: i,
+                    weight: *weight,
+                    min_depth: *min_depth,
+                    max_depth: *max_depth,
+                })
+                .collect(),
+        };
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mut seen = vec![false; entries.len()];
+        for _ in 0..400 {
+            match table.roll(&mut rng, depth) {
+                Some(i) => {
+                    prop_assert!(table.entries[i].allowed_at(depth));
+                    seen[i] = true;
+                }
+                None => prop_assert!(table.entries.iter().all(|e| !e.allowed_at(depth))),
+            }
+        }
+        // The first and last entries used to be out of reach, with 400
+        // rolls every allowed entry should come up
+        for (i, entry) in table.entries.iter().enumerate() {
+            prop_assert_eq!(seen[i], entry.allowed_at(depth));
+        }
+    }
+}
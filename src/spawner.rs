@@ -1,3 +0,0 @@
-use rltk::{RGB, RandomNumberGenerator};
-
-
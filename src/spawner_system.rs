@@ -0,0 +1,198 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use crate::{
+    components::{MyTurn, Name, Position, SpawnedBy, Spawner},
+    gamelog::GameLog,
+    map::{Map, TileType},
+    rng::SpawnRng,
+    state::RunState,
+};
+use rltk::{Algorithm2D, Point};
+use specs::prelude::*;
+
+pub struct SpawnerSystem {}
+
+impl<'a> System<'a> for SpawnerSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, SpawnRng>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Spawner>,
+        ReadStorage<'a, MyTurn>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            runstate,
+            mut map,
+            mut rng,
+            mut game_log,
+            mut spawners,
+            my_turn,
+            positions,
+            names,
+            lazy,
+        ) = data;
+
+        // Spawners act alongside the monsters, on their own turns
+        if *runstate != RunState::Ticking {
+            return;
+        }
+
+        for (parent, spawner, _turn, pos, name) in
+            (&entities, &mut spawners, &my_turn, &positions, &names).join()
+        {
+            if spawner.children >= spawner.max_children {
+                continue;
+            }
+            spawner.progress += spawner.rate;
+            if spawner.progress < 1.0 {
+                continue;
+            }
+
+            let free = free_neighbours(&map, pos.point);
+            if free.is_empty() {
+                // Boxed in, hold the spawn until a tile frees up
+                spawner.progress = 1.0;
+                continue;
+            }
+            let target = free[rng.0.roll_dice(1, free.len() as i32) as usize - 1];
+            let spec = spawner.spec.clone().with_point(target);
+            let child_name = Name::from(spec.clone()).name;
+
+            let child = spec
+                .with_components(lazy.create_entity(&entities))
+                .with(SpawnedBy { parent })
+                .build();
+            for item in spec.equipment.iter() {
+                item.equipped_by(lazy.create_entity(&entities), child).build();
+            }
+
+            // The child only shows up at the next maintain, so claim the
+            // tile now to stop anything else from moving into it
+            let idx = map.point2d_to_index(target);
+            map.blocked[idx] = true;
+            spawner.children += 1;
+            spawner.progress -= 1.0;
+
+            if map.visible_tiles[map.point2d_to_index(pos.point)] {
+                game_log
+                    .entries
+                    .push(format!("The {} spawns a {}", name.name, child_name));
+            }
+        }
+    }
+}
+
+fn free_neighbours(map: &Map, origin: Point) -> Vec<Point> {
+    let mut free = Vec::new();
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let p = Point::new(origin.x + dx, origin.y + dy);
+            if p == origin || !map.in_bounds(p) {
+                continue;
+            }
+            let idx = map.point2d_to_index(p);
+            if !map.blocked[idx] && map.tiles[idx] == TileType::Floor {
+                free.push(p);
+            }
+        }
+    }
+    free
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+    #[test]
+    fn test_broods_stay_small_close_and_die_with_the_parent(
+        seed in any::<u64>(),
+        walls in prop::collection::vec(any::<bool>(), 9),
+        max_children in 0..6i32,
+        rate in 0.1..2.0f32,
+        deaths in prop::collection::vec(any::<bool>(), 1..40),
+    ) {
+        use crate::components::{register_all_components, CombatStats};
+        use crate::damage_system::delete_the_dead;
+        use crate::map::new_map;
+        use crate::map_index_system::MapIndexingSystem;
+        use rltk::RandomNumberGenerator;
+
+        let mut ecs = World::new();
+        register_all_components(&mut ecs);
+        ecs.insert(RunState::Ticking);
+        ecs.insert(SpawnRng(RandomNumberGenerator::seeded(seed)));
+        ecs.insert(GameLog { entries: Vec::new() });
+
+        // The parent sits in the middle of a 3x3 patch, some of it wall
+        let mut map = new_map(5, 5);
+        let origin = Point::new(2, 2);
+        for (i, wall) in walls.iter().enumerate() {
+            let p = Point::new(1 + i as i32 % 3, 1 + i as i32 / 3);
+            let idx = map.point2d_to_index(p);
+            map.tiles[idx] = if *wall && p != origin { TileType::Wall } else { TileType::Floor };
+        }
+        ecs.insert(map);
+
+        let spec = crate::raws::test_raws().monsters[0].clone();
+        let parent = ecs
+            .create_entity()
+            .with(Spawner { rate, progress: 0.0, spec, max_children, children: 0 })
+            .with(Position { point: origin })
+            .with(Name { name: "Parent".to_string() })
+            .with(CombatStats { max_hp: 1, hp: 1, defense: 0, power: 0 })
+            .with(MyTurn {})
+            .build();
+        // delete_the_dead wants a player, one that isn't in the way
+        let bystander = ecs.create_entity().build();
+        ecs.insert(bystander);
+
+        let brood = |ecs: &World| -> Vec<(Entity, Point)> {
+            (&ecs.entities(), &ecs.read_storage::<SpawnedBy>(), &ecs.read_storage::<Position>())
+                .join()
+                .filter(|(_, s, _)| s.parent == parent)
+                .map(|(e, _, p)| (e, p.point))
+                .collect()
+        };
+
+        for dies in deaths {
+            MapIndexingSystem {}.run_now(&ecs);
+            SpawnerSystem {}.run_now(&ecs);
+            ecs.maintain();
+
+            let children = brood(&ecs);
+            prop_assert!(children.len() as i32 <= max_children);
+            prop_assert_eq!(ecs.read_storage::<Spawner>().get(parent).unwrap().children, children.len() as i32);
+            let map = ecs.fetch::<Map>();
+            for (i, (_, at)) in children.iter().enumerate() {
+                prop_assert!(*at != origin && (at.x - origin.x).abs() <= 1 && (at.y - origin.y).abs() <= 1);
+                prop_assert_eq!(map.tiles[map.point2d_to_index(*at)], TileType::Floor);
+                prop_assert!(children.iter().skip(i + 1).all(|(_, other)| other != at));
+            }
+            drop(map);
+
+            // A child that dies frees a place in the brood
+            if let (true, Some((child, _))) = (dies, children.first()) {
+                ecs.write_storage::<CombatStats>()
+                    .insert(*child, CombatStats { max_hp: 1, hp: 0, defense: 0, power: 0 })
+                    .unwrap();
+                delete_the_dead(&mut ecs);
+                ecs.maintain();
+                prop_assert_eq!(ecs.read_storage::<Spawner>().get(parent).unwrap().children, children.len() as i32 - 1);
+            }
+        }
+
+        ecs.write_storage::<CombatStats>().get_mut(parent).unwrap().hp = 0;
+        delete_the_dead(&mut ecs);
+        ecs.maintain();
+        prop_assert!(!ecs.is_alive(parent));
+        prop_assert!(brood(&ecs).is_empty());
+        prop_assert_eq!((&ecs.read_storage::<SpawnedBy>()).join().count(), 0);
+    }
+}
@@ -1,38 +1,304 @@
-use proc_macro::TokenStream;
-use quote::quote;
-use syn;
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Error, Expr, Fields, GenericArgument, Ident, Path, PathArguments, Result,
+    Token, Type,
+};
 
-// This macro implements from<*Spec> for Component
-pub fn impl_spec_macro(ast: &syn::DeriveInput) -> TokenStream {
+// What a #[spec(...)] on the struct asks for
+enum StructArg {
+    // A component every entity built from the spec gets, as written
+    With(Box<Expr>),
+    // A component built by a hand written From<Spec>
+    From(Path),
+    // Fields of this component the spec doesn't fill come from Default
+    Default(Path),
+}
+
+// What a #[spec(...)] on a field asks for
+enum FieldArg {
+    // The field fills the field of the same name, or `field`, of a component
+    Into(Path),
+    Field(Ident),
+    // The field is a component already, or an Option of one
+    Component(Ident),
+}
+
+impl Parse for StructArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        match key.to_string().as_str() {
+            "with" => Ok(StructArg::With(input.parse()?)),
+            "from" => Ok(StructArg::From(input.parse()?)),
+            "default" => Ok(StructArg::Default(input.parse()?)),
+            _ => Err(Error::new(
+                key.span(),
+                format!(
+                    "unknown spec argument `{}`, expected `with`, `from` or `default`",
+                    key
+                ),
+            )),
+        }
+    }
+}
+
+impl Parse for FieldArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: Ident = input.parse()?;
+        if key == "component" {
+            return Ok(FieldArg::Component(key));
+        }
+        match key.to_string().as_str() {
+            "into" => {
+                input.parse::<Token![=]>()?;
+                Ok(FieldArg::Into(input.parse()?))
+            }
+            "field" => {
+                input.parse::<Token![=]>()?;
+                Ok(FieldArg::Field(input.parse()?))
+            }
+            _ => Err(Error::new(
+                key.span(),
+                format!(
+                    "unknown spec argument `{}`, expected `into`, `field` or `component`",
+                    key
+                ),
+            )),
+        }
+    }
+}
+
+fn spec_args<T: Parse>(attrs: &[syn::Attribute]) -> Result<Vec<T>> {
+    let mut args = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("spec")) {
+        args.extend(attr.parse_args_with(Punctuated::<T, Token![,]>::parse_terminated)?);
+    }
+    Ok(args)
+}
+
+// The T in Option<T>, if `ty` is one
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let last = path.path.segments.last()?;
+    if last.ident != "Option" {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A component that several fields of the spec are gathered into
+struct Group {
+    component: Path,
+    fields: Vec<(Ident, Ident)>,
+}
+
+// Adds `error` to the ones found so far, so they are all reported at once
+pub(crate) fn push_error(errors: &mut Option<Error>, error: Error) {
+    match errors {
+        Some(errors) => errors.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+// Implements From<*Spec> for every component its fields are marked with,
+// and build_entity/with_components to make an entity out of the spec.
+//
+//   #[derive(FromSpec)]
+//   #[spec(with = BlocksTile {})]
+//   struct MonsterSpec {
+//       #[spec(into = Renderable)] glyph: FontCharType,
+//       #[spec(into = Energy, field = speed)] speed: i32,
+//       #[spec(component)] combat_stats: Option<CombatStats>,
+//   }
+//
+// Components are named by the paths written in the attributes, so they
+// only need to be in scope where the spec is. Everything else the
+// generated code uses is written out in full.
+pub fn impl_spec_macro(ast: &DeriveInput) -> Result<TokenStream> {
     let name = &ast.ident;
-    let gen = quote! {
-        impl From<#name> for Position {
-            fn from(val: #name) -> Self {
-                Position { point: val.point }
-            }
-        }
-    
-        impl From<#name> for Renderable {
-            fn from(val: #name) -> Renderable {
-                Renderable {
-                    glyph: val.glyph,
-                    fg: val.fg,
-                    bg: val.bg,
+    let fields = match &ast.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields,
+            other => {
+                return Err(Error::new(
+                    other.span(),
+                    "FromSpec needs a struct with named fields",
+                ))
+            }
+        },
+        Data::Enum(data) => {
+            return Err(Error::new(
+                data.enum_token.span,
+                "FromSpec only works on structs, not enums",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span,
+                "FromSpec only works on structs, not unions",
+            ))
+        }
+    };
+
+    let mut errors: Option<Error> = None;
+    let struct_args: Vec<StructArg> = spec_args(&ast.attrs).unwrap_or_else(|e| {
+        push_error(&mut errors, e);
+        Vec::new()
+    });
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut impls = Vec::new();
+    let mut withs = Vec::new();
+
+    for arg in struct_args.iter() {
+        match arg {
+            StructArg::With(expr) => withs.push(quote_spanned! {expr.span()=> .with(#expr) }),
+            StructArg::From(component) => withs.push(quote_spanned! {component.span()=>
+                .with(<#component as ::core::convert::From<#name>>::from(
+                    ::core::clone::Clone::clone(self),
+                ))
+            }),
+            StructArg::Default(_) => {}
+        }
+    }
+
+    for field in fields.named.iter() {
+        let ident = field.ident.clone().unwrap();
+        let args: Vec<FieldArg> = match spec_args(&field.attrs) {
+            Ok(args) => args,
+            Err(e) => {
+                push_error(&mut errors, e);
+                continue;
+            }
+        };
+
+        let mut into: Option<Path> = None;
+        let mut rename: Option<Ident> = None;
+        for arg in args {
+            match arg {
+                FieldArg::Into(path) if into.is_some() => push_error(
+                    &mut errors,
+                    Error::new(path.span(), "a field can only go into one component"),
+                ),
+                FieldArg::Into(path) => into = Some(path),
+                FieldArg::Field(f) => rename = Some(f),
+                FieldArg::Component(key) => {
+                    let ty = &field.ty;
+                    impls.push(quote_spanned! {key.span()=>
+                        impl ::core::convert::From<#name> for #ty {
+                            fn from(val: #name) -> #ty {
+                                val.#ident
+                            }
+                        }
+                    });
+                    if option_inner(ty).is_some() {
+                        withs.push(quote_spanned! {key.span()=>
+                            .maybe_with(::core::clone::Clone::clone(&self.#ident))
+                        });
+                    } else {
+                        withs.push(quote_spanned! {key.span()=>
+                            .with(::core::clone::Clone::clone(&self.#ident))
+                        });
+                    }
                 }
             }
         }
-    
-        impl From<#name> for Option<CombatStats> {
-            fn from(val: #name) -> Option<CombatStats> {
-               Some(val.combat_stats)
+
+        match (into, rename) {
+            (Some(component), rename) => {
+                let target = rename.unwrap_or_else(|| ident.clone());
+                match groups.iter_mut().find(|g| g.component == component) {
+                    Some(group) => group.fields.push((target, ident)),
+                    None => {
+                        withs.push(quote_spanned! {component.span()=>
+                            .with(<#component as ::core::convert::From<#name>>::from(
+                                ::core::clone::Clone::clone(self),
+                            ))
+                        });
+                        groups.push(Group {
+                            component,
+                            fields: vec![(target, ident)],
+                        });
+                    }
+                }
             }
+            (None, Some(rename)) => push_error(
+                &mut errors,
+                Error::new(rename.span(), "`field` needs an `into = Component` to go with it"),
+            ),
+            (None, None) => {}
         }
-    
-        impl From<#name> for Name {
-            fn from(val: #name) -> Name {
-                Name { name: val.name }
+    }
+
+    let mut defaults = Vec::new();
+    for arg in struct_args.iter() {
+        if let StructArg::Default(component) = arg {
+            if groups.iter().any(|g| g.component == *component) {
+                defaults.push(component);
+            } else {
+                push_error(
+                    &mut errors,
+                    Error::new(
+                        component.span(),
+                        "no field goes into this component, so there is nothing to default",
+                    ),
+                );
             }
         }
-    };
-    gen.into()
-}
\ No newline at end of file
+    }
+
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    for group in groups.iter() {
+        let component = &group.component;
+        // Spanned at the spec's own fields, so a field the component
+        // doesn't have is reported on the line that names it
+        let inits = group.fields.iter().map(|(target, source)| {
+            quote_spanned! {target.span()=> #target: val.#source, }
+        });
+        let rest = if defaults.contains(&component) {
+            quote! { ..::core::default::Default::default() }
+        } else {
+            quote! {}
+        };
+        impls.push(quote! {
+            impl ::core::convert::From<#name> for #component {
+                fn from(val: #name) -> #component {
+                    #component {
+                        #(#inits)*
+                        #rest
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(quote! {
+        #(#impls)*
+
+        impl #name {
+            // Everything an entity made from this spec is made of. Takes
+            // any builder, so systems can pass a LazyBuilder.
+            pub fn with_components<B: ::specs::Builder>(&self, builder: B) -> B {
+                builder #(#withs)*
+            }
+
+            pub fn build_entity(&self, ecs: &mut ::specs::World) -> ::specs::Entity {
+                use ::specs::{Builder, WorldExt};
+                self.with_components(ecs.create_entity()).build()
+            }
+        }
+    })
+}
@@ -1,64 +1,209 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use crate::camera::render_camera;
 use crate::components::*;
 use crate::damage_system::delete_the_dead;
-use crate::damage_system::DamageSystem;
-use crate::gui::draw_ui;
+use crate::dispatcher::default_system_registry;
+use crate::gamelog::GameLog;
+use crate::gui::{
+    clicked_target, draw_ui, main_menu, show_inventory, show_level_up, show_targeting,
+    MainMenuResult,
+};
 use crate::map::*;
-use crate::map_index_system::MapIndexingSystem;
-use crate::melee_combat_system::MeleeCombatSystem;
-use crate::monster::MonsterSpec;
-use crate::monster::SpawnerSpec;
-use crate::monster_ai_system::MonsterAI;
-use crate::player::player_input;
-use crate::visibility_system::VisibilitySystem;
-use rltk::{GameState, Point, Rltk, RGB};
+use crate::monster::populate_rooms;
+use crate::action::Action;
+use crate::player::{inventory_input, level_up_input, player_action, player_input, targeting_input};
+use crate::raws::Raws;
+use crate::rng::{insert_rng_streams, level_rng, MasterSeed, Stream};
+use crate::replay::{next_input, start_recording, ReplayPlayer};
+use crate::saveload::{delete_save, load_game, save_game};
+use rltk::{GameState, Point, Rltk, VirtualKeyCode, RGB};
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum RunState {
     PreRun,
     // In awaiting input the player only declares their intent
     AwaitingInput,
     // PlayerTurn is where the changes actually propogate into the game
     PlayerTurn,
-    MonsterTurn,
+    // The clock runs and everyone else acts until the player's turn
+    // comes around again
+    Ticking,
     // A hack, to allow me to show the whole map easily. Probably should make
     // a debug mode instead.
     RevealMap,
+    // The player took the stairs, build the level below or above
+    NextLevel,
+    PreviousLevel,
+    // An inventory is open, the next key picks an item to use or drop
+    ShowInventory,
+    ShowDropItem,
+    // Aiming whatever the Targeting resource holds
+    ShowTargeting,
+    // The player has the xp for a level and picks what it raises
+    LevelUp,
+    // Shown at startup when there is a save to continue
+    MainMenu,
+    SaveGame,
     GameOver,
 }
+
+impl RunState {
+    // The states that wait for a key before anything else happens
+    pub fn awaits_input(self) -> bool {
+        matches!(
+            self,
+            RunState::AwaitingInput
+                | RunState::ShowInventory
+                | RunState::ShowDropItem
+                | RunState::ShowTargeting
+                | RunState::LevelUp
+        )
+    }
+}
+
 pub struct State {
     pub ecs: World,
+    pub dispatcher: Dispatcher<'static, 'static>,
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
         ctx.cls();
-        let mut newrunstate;
-        {
-            let runstate = self.ecs.fetch::<RunState>();
-            newrunstate = *runstate;
+        // Closing the window saves the run, like pressing escape does
+        while let Some(event) = rltk::INPUT.lock().pop() {
+            if event == rltk::BEvent::CloseRequested {
+                self.save_and_quit(ctx);
+                return;
+            }
+        }
+
+        let runstate = *self.ecs.fetch::<RunState>();
+        match runstate {
+            RunState::MainMenu => {
+                let newrunstate = self.main_menu(ctx);
+                *self.ecs.write_resource::<RunState>() = newrunstate;
+                if newrunstate == RunState::MainMenu {
+                    return;
+                }
+            }
+            RunState::SaveGame => {
+                self.save_and_quit(ctx);
+                return;
+            }
+            state if state.awaits_input() => {
+                let pressed = ctx.key.or_else(|| clicked_target(&self.ecs, ctx));
+                let key = next_input(&mut self.ecs, pressed);
+                self.step(key);
+            }
+            _ => {
+                self.step(None);
+            }
+        }
+
+        render_camera(&self.ecs, ctx);
+        draw_ui(&self.ecs, ctx);
+        match *self.ecs.fetch::<RunState>() {
+            RunState::ShowInventory => show_inventory(&self.ecs, ctx, "Use which item?"),
+            RunState::ShowDropItem => show_inventory(&self.ecs, ctx, "Drop which item?"),
+            RunState::ShowTargeting => show_targeting(&self.ecs, ctx),
+            RunState::LevelUp => show_level_up(&self.ecs, ctx),
+            _ => {}
         }
+    }
+}
+
+impl State {
+    // A fresh run from `seed` with the player on the first level. None of
+    // it needs a window, main adds what rendering needs on top.
+    pub fn new_run(
+        seed: u64,
+        mgc: MapGenConfig,
+        raws: Raws,
+        width: usize,
+        height: usize,
+    ) -> Result<State, MapGenError> {
+        let dispatcher = default_system_registry()
+            .build()
+            .expect("The default systems are out of order");
+        let mut gs = State {
+            ecs: World::new(),
+            dispatcher,
+        };
+        register_all_components(&mut gs.ecs);
+        insert_rng_streams(&mut gs.ecs, seed);
+        gs.ecs.insert(RunState::PreRun);
+        gs.ecs.insert(GameLog {
+            entries: vec![
+                format!("Seed: {}", seed),
+                "Welcome to your nightmare".to_string(),
+            ],
+        });
+        gs.ecs.insert(mgc);
+        gs.ecs.insert(raws);
+        gs.ecs.insert(new_map(width, height));
+
+        let start = gs.build_level(1)?;
+        create_player(&mut gs, start.x, start.y);
+        gs.ecs.insert(start);
+        Ok(gs)
+    }
+
+    // Advances the game by one frame. `key` is only looked at while the
+    // game waits for the player. Everything but drawing and the menus
+    // happens here, so the game runs without a window too.
+    pub fn step(&mut self, key: Option<VirtualKeyCode>) -> RunState {
+        let mut newrunstate = *self.ecs.fetch::<RunState>();
         match newrunstate {
             RunState::PreRun => {
                 self.run_systems();
-                newrunstate = RunState::AwaitingInput;
+                newrunstate = RunState::Ticking;
             }
             RunState::AwaitingInput => {
-                newrunstate = player_input(self, ctx); 
+                newrunstate = player_input(self, key);
+            }
+            RunState::ShowInventory | RunState::ShowDropItem => {
+                newrunstate = inventory_input(self, key, newrunstate);
+            }
+            RunState::ShowTargeting => {
+                newrunstate = targeting_input(self, key);
+            }
+            RunState::LevelUp => {
+                newrunstate = level_up_input(self, key);
             }
             RunState::PlayerTurn => {
                 self.run_systems();
-                newrunstate = RunState::MonsterTurn;
+                newrunstate = RunState::Ticking;
             }
             RunState::RevealMap => {
                 let mut map = self.ecs.write_resource::<Map>();
                 map.reveal_map();
                 newrunstate = RunState::AwaitingInput;
             }
-            RunState::MonsterTurn => {
+            RunState::Ticking => {
                 self.run_systems();
-                newrunstate= RunState::AwaitingInput;
+                if self.is_players_turn() {
+                    newrunstate = RunState::AwaitingInput;
+                }
             },
+            RunState::NextLevel | RunState::PreviousLevel => {
+                let delta = if newrunstate == RunState::NextLevel { 1 } else { -1 };
+                newrunstate = match self.change_level(delta) {
+                    Ok(()) => RunState::PreRun,
+                    // The player stays where they are and can go on playing
+                    Err(e) => {
+                        self.ecs
+                            .fetch_mut::<GameLog>()
+                            .entries
+                            .push(format!("The stairs lead nowhere, {}", e));
+                        RunState::AwaitingInput
+                    }
+                };
+            }
+            // The front end deals with these, they need a window
+            RunState::MainMenu | RunState::SaveGame => {}
             RunState::GameOver => {
                 // Do nothing the game is over for the moment
 
@@ -69,74 +214,192 @@ impl GameState for State {
             *runwriter = newrunstate;
         }
 
-
         delete_the_dead(&mut self.ecs);
-        let map = self.ecs.fetch::<Map>();
-        draw_map(&map, ctx);
+        newrunstate
+    }
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        for (pos, render) in (&positions, &renderables).join() {
-            if !map.visible_tiles[xy_idx(pos.point.x, pos.point.y)] {
-                continue;
+    // Runs frames until the game needs the player again, or can't go on
+    // without a front end
+    pub fn run_until_input(&mut self) -> RunState {
+        loop {
+            let runstate = *self.ecs.fetch::<RunState>();
+            match runstate {
+                state if state.awaits_input() => return runstate,
+                RunState::MainMenu | RunState::SaveGame | RunState::GameOver => return runstate,
+                _ => {
+                    self.step(None);
+                }
             }
-            ctx.set(pos.point.x, pos.point.y, render.fg, render.bg, render.glyph);
         }
-        draw_ui(&self.ecs, ctx);
     }
-}
 
-impl State {
-    fn run_systems(&mut self) {
-        let mut vis = VisibilitySystem {};
-        vis.run_now(&self.ecs);
-        let mut monster = MonsterAI {};
-        monster.run_now(&self.ecs);
-        let mut mapindex = MapIndexingSystem {};
-        mapindex.run_now(&self.ecs);
-        let mut melee_combat_system = MeleeCombatSystem {};
-        melee_combat_system.run_now(&self.ecs);
-        let mut damage = DamageSystem {};
-        damage.run_now(&self.ecs);
+    // Plays one key the way the keyboard would and waits for the next
+    pub fn play_turn(&mut self, key: VirtualKeyCode) -> RunState {
+        if !self.run_until_input().awaits_input() {
+            return *self.ecs.fetch::<RunState>();
+        }
+        self.step(Some(key));
+        self.run_until_input()
+    }
+
+    // Same as play_turn, for bots that decide on actions rather than keys
+    pub fn play_action(&mut self, action: Action) -> RunState {
+        if self.run_until_input() != RunState::AwaitingInput {
+            return *self.ecs.fetch::<RunState>();
+        }
+        let runstate = player_action(&mut self.ecs, action);
+        *self.ecs.write_resource::<RunState>() = runstate;
+        self.run_until_input()
+    }
+
+    // Plays keys until they run out or the run stops taking input
+    pub fn play<I: IntoIterator<Item = VirtualKeyCode>>(&mut self, keys: I) -> RunState {
+        let mut runstate = self.run_until_input();
+        for key in keys {
+            if !runstate.awaits_input() {
+                break;
+            }
+            runstate = self.play_turn(key);
+        }
+        runstate
+    }
+
+    fn main_menu(&mut self, ctx: &mut Rltk) -> RunState {
+        match main_menu(ctx) {
+            MainMenuResult::NoSelection => RunState::MainMenu,
+            MainMenuResult::NewGame => {
+                start_recording(&mut self.ecs);
+                RunState::PreRun
+            }
+            MainMenuResult::Continue => match load_game(&mut self.ecs) {
+                Ok(()) => {
+                    // One life per run, the save goes once it is loaded
+                    delete_save();
+                    *self.ecs.fetch::<RunState>()
+                }
+                Err(e) => {
+                    self.ecs
+                        .fetch_mut::<GameLog>()
+                        .entries
+                        .push(format!("Unable to continue, {}", e));
+                    RunState::PreRun
+                }
+            },
+        }
+    }
+
+    fn save_and_quit(&mut self, ctx: &mut Rltk) {
+        // A dead player has nothing to come back to, and a replay must
+        // not overwrite the save of the run being played
+        let replaying = self.ecs.has_value::<ReplayPlayer>();
+        let runstate = *self.ecs.fetch::<RunState>();
+        match runstate {
+            RunState::GameOver | RunState::MainMenu => {}
+            _ if replaying => {}
+            _ => {
+                // Escape was pressed while it was the player's turn, and
+                // it still is when the game is continued. Menus and aiming
+                // are closed, the resources they keep aren't saved. A
+                // level up keeps nothing aside and is still owed.
+                if runstate == RunState::SaveGame
+                    || (runstate.awaits_input() && runstate != RunState::LevelUp)
+                {
+                    *self.ecs.write_resource::<RunState>() = RunState::AwaitingInput;
+                }
+                if let Err(e) = save_game(&mut self.ecs) {
+                    eprintln!("Unable to save the game: {}", e);
+                }
+            }
+        }
+        ctx.quit();
+    }
 
+    pub(crate) fn is_players_turn(&self) -> bool {
+        let player = *self.ecs.fetch::<Entity>();
+        self.ecs.read_storage::<MyTurn>().contains(player)
+    }
+
+    fn run_systems(&mut self) {
+        self.dispatcher.dispatch(&self.ecs);
         self.ecs.maintain();
     }
-}
 
-pub fn create_spawner(state: &mut State, ss:SpawnerSpec) {
-    state
-        .ecs
-        .create_entity()
-        .with(BlocksTile {})
-        .with(Position::from(ss.clone()))
-        .with(Viewshed {
-            visible_tiles: Vec::new(),
-            range: 8,
-            dirty: true,
-        })
-        .with(Name::from(ss.clone()))
-        .with(Monster {mobile: false})
-        .maybe_with(Option::<CombatStats>::from(ss.clone()))
-        .with(Renderable::from(ss.clone()))
-        .build();
-}
+    // Replaces the current Map resource with a freshly generated level
+    // of the same size and populates it. Returns where the player should
+    // arrive when coming down into it.
+    pub fn build_level(&mut self, depth: i32) -> Result<Point, MapGenError> {
+        let map = self.generate_level(depth)?;
+        Ok(self.populate_level(map))
+    }
 
-pub fn create_monster(state: &mut State, ms: MonsterSpec) {
-    state
-        .ecs
-        .create_entity()
-        .with(BlocksTile {})
-        .with(Position::from(ms.clone()))
-        .with(Viewshed {
-            visible_tiles: Vec::new(),
-            range: 8,
-            dirty: true,
-        })
-        .with(Name::from(ms.clone()))
-        .with(Monster {mobile: true})
-        .maybe_with(Option::<CombatStats>::from(ms.clone()))
-        .with(Renderable::from(ms.clone()))
-        .build();
+    // Lays out the level at `depth` without touching the world, so a
+    // config that can't be built leaves everything as it was
+    fn generate_level(&self, depth: i32) -> Result<Map, MapGenError> {
+        let mgc = *self.ecs.fetch::<MapGenConfig>();
+        let mut map = {
+            let old = self.ecs.fetch::<Map>();
+            new_map(old.width as usize, old.height as usize)
+        };
+        map.depth = depth;
+
+        let seed = self.ecs.fetch::<MasterSeed>().0;
+        make_dungeon(&mgc, &mut level_rng(seed, Stream::Map, depth), &mut map)?;
+        Ok(map)
+    }
+
+    fn populate_level(&mut self, map: Map) -> Point {
+        let seed = self.ecs.fetch::<MasterSeed>().0;
+        populate_rooms(self, &mut level_rng(seed, Stream::Monsters, map.depth), &map);
+
+        let start = find_starting_position(&map);
+        self.ecs.insert(map);
+        start
+    }
+
+    // Everything but the player and what they carry or wear is left
+    // behind on the old level. The new level is laid out first, if it
+    // can't be the player stays on the old one.
+    fn change_level(&mut self, delta: i32) -> Result<(), MapGenError> {
+        let depth = self.ecs.fetch::<Map>().depth + delta;
+        let map = self.generate_level(depth)?;
+        let player = *self.ecs.fetch::<Entity>();
+        let to_delete: Vec<Entity> = {
+            let backpack = self.ecs.read_storage::<InBackpack>();
+            let equipped = self.ecs.read_storage::<Equipped>();
+            self.ecs
+                .entities()
+                .join()
+                .filter(|e| *e != player)
+                .filter(|e| backpack.get(*e).is_none_or(|b| b.owner != player))
+                .filter(|e| equipped.get(*e).is_none_or(|w| w.owner != player))
+                .collect()
+        };
+        self.ecs
+            .delete_entities(&to_delete)
+            .expect("Unable to delete the old level");
+
+        let mut arrival = self.populate_level(map);
+        if delta < 0 {
+            let map = self.ecs.fetch::<Map>();
+            arrival = find_tile(&map, TileType::DownStairs).unwrap_or(arrival);
+        }
+
+        *self.ecs.write_resource::<Point>() = arrival;
+        if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player) {
+            pos.point = arrival;
+        }
+        if let Some(vs) = self.ecs.write_storage::<Viewshed>().get_mut(player) {
+            vs.dirty = true;
+        }
+
+        let mut gamelog = self.ecs.fetch_mut::<GameLog>();
+        if delta > 0 {
+            gamelog.entries.push(format!("You descend to depth {}", depth));
+        } else {
+            gamelog.entries.push(format!("You climb back up to depth {}", depth));
+        }
+        Ok(())
+    }
 }
 
 pub fn create_player(state: &mut State, x: i32, y: i32) {
@@ -162,6 +425,11 @@ pub fn create_player(state: &mut State, x: i32, y: i32) {
             defense: 2,
             power: 5,
         })
+        .with(Energy {
+            speed: 100,
+            energy: 0,
+        })
+        .with(Experience { level: 1, xp: 0 })
         .with(Renderable {
             glyph: rltk::to_cp437('@'),
             fg: RGB::named(rltk::YELLOW),
@@ -170,3 +438,70 @@ pub fn create_player(state: &mut State, x: i32, y: i32) {
         .build();
     state.ecs.insert(player)
 }
+
+// A fresh run on the default map and the test raws
+#[cfg(test)]
+pub fn test_state(seed: u64) -> State {
+    State::new_run(seed, default_map_config(), crate::raws::test_raws(), 80, 50).unwrap()
+}
+
+#[cfg(test)]
+pub fn any_command() -> impl Strategy<Value = VirtualKeyCode> {
+    prop::sample::select(vec![
+        VirtualKeyCode::H,
+        VirtualKeyCode::J,
+        VirtualKeyCode::K,
+        VirtualKeyCode::L,
+        VirtualKeyCode::Y,
+        VirtualKeyCode::U,
+        VirtualKeyCode::N,
+        VirtualKeyCode::M,
+        VirtualKeyCode::Period,
+        VirtualKeyCode::Comma,
+        VirtualKeyCode::Space,
+        VirtualKeyCode::G,
+        VirtualKeyCode::I,
+        VirtualKeyCode::D,
+        VirtualKeyCode::A,
+        VirtualKeyCode::B,
+        VirtualKeyCode::C,
+        VirtualKeyCode::F,
+        VirtualKeyCode::Tab,
+        VirtualKeyCode::Return,
+        VirtualKeyCode::Key1,
+    ])
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_stairs_to_an_unbuildable_level_go_nowhere(seed in any::<u64>(), down in any::<bool>()) {
+        let mut gs = test_state(seed);
+        gs.run_until_input();
+        let before: Vec<Entity> = gs.ecs.entities().join().collect();
+        let depth = gs.ecs.fetch::<Map>().depth;
+
+        let mut broken = default_map_config();
+        broken.num_rooms = 0;
+        gs.ecs.insert(broken);
+        *gs.ecs.write_resource::<RunState>() = if down { RunState::NextLevel } else { RunState::PreviousLevel };
+        prop_assert_eq!(gs.step(None), RunState::AwaitingInput);
+
+        prop_assert_eq!(gs.ecs.fetch::<Map>().depth, depth);
+        prop_assert_eq!(gs.ecs.entities().join().collect::<Vec<_>>(), before);
+        let expected = format!("The stairs lead nowhere, {}", MapGenError::NoRooms);
+        prop_assert_eq!(gs.ecs.fetch::<GameLog>().entries.last().cloned(), Some(expected));
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(8))]
+    #[test]
+    fn test_headless_runs_thousands_of_turns(seed in any::<u64>(), keys in prop::collection::vec(any_command(), 2000)) {
+        let mut gs = test_state(seed);
+        let runstate = gs.play(keys);
+        prop_assert!(runstate.awaits_input() || runstate == RunState::GameOver);
+    }
+}
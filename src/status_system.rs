@@ -0,0 +1,230 @@
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use crate::components::ActiveEffect;
+use specs::prelude::*;
+
+use crate::{
+    components::{Effect, GainsEffect, MyTurn, Name, StatusEffects, SufferDamage},
+    gamelog::GameLog,
+    inventory_system::who,
+    state::RunState,
+};
+
+// "You are" or "The Scold is"
+fn is(entity: Entity, player: Entity, names: &ReadStorage<Name>) -> String {
+    let name = who(entity, player, names);
+    if name == "You" {
+        "You are".to_string()
+    } else {
+        format!("{} is", name)
+    }
+}
+
+// Runs down everyone's effects as their turns come up. Poison hurts,
+// sleep takes the turn away and whatever runs out is dropped.
+pub struct StatusSystem {}
+
+impl<'a> System<'a> for StatusSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, MyTurn>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player, runstate, mut gamelog, mut statuses, mut my_turn, mut damage, names) =
+            data;
+
+        // Turns are only handed out while the clock runs
+        if *runstate != RunState::Ticking {
+            return;
+        }
+
+        let mut asleep = Vec::new();
+        for (entity, status, _turn) in (&entities, &mut statuses, &my_turn).join() {
+            if status.has(Effect::Sleep) {
+                asleep.push(entity);
+            }
+            for active in status.active.iter_mut() {
+                if let Effect::Poison { damage: amount } = active.effect {
+                    SufferDamage::new_damage(&mut damage, entity, amount, active.from);
+                }
+                active.turns -= 1;
+            }
+            for expired in status.active.iter().filter(|a| a.turns <= 0) {
+                gamelog.entries.push(format!(
+                    "{} no longer {}.",
+                    is(entity, *player, &names),
+                    expired.effect.adjective()
+                ));
+            }
+            status.active.retain(|a| a.turns > 0);
+        }
+        for entity in asleep {
+            my_turn.remove(entity);
+        }
+    }
+}
+
+// Adds whatever GainsEffect piled up this turn. The same effect again
+// lasts as long as the longer of the two and is as strong as the stronger.
+pub struct EffectSystem {}
+
+impl<'a> System<'a> for EffectSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, GainsEffect>,
+        WriteStorage<'a, StatusEffects>,
+        ReadStorage<'a, Name>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player, mut gamelog, mut gains, mut statuses, names) = data;
+
+        for (entity, gained) in (&entities, &gains).join() {
+            let status = statuses
+                .entry(entity)
+                .expect("Unable to reach the status effects")
+                .or_insert_with(StatusEffects::default);
+            for new in gained.pending.iter() {
+                match status.active.iter_mut().find(|a| a.effect.same_kind(&new.effect)) {
+                    Some(active) => {
+                        active.turns = active.turns.max(new.turns);
+                        // The latest dose takes the blame for what follows
+                        if new.from.is_some() {
+                            active.from = new.from;
+                        }
+                        if let (Effect::Poison { damage: old }, Effect::Poison { damage }) =
+                            (&mut active.effect, new.effect)
+                        {
+                            *old = (*old).max(damage);
+                        }
+                    }
+                    None => {
+                        status.active.push(*new);
+                        gamelog.entries.push(format!(
+                            "{} {}.",
+                            is(entity, *player, &names),
+                            new.effect.adjective()
+                        ));
+                    }
+                }
+            }
+        }
+        gains.clear();
+    }
+}
+
+#[cfg(test)]
+fn any_effect() -> impl Strategy<Value = ActiveEffect> {
+    let effect = prop_oneof![
+        (1..4i32).prop_map(|damage| Effect::Poison { damage }),
+        Just(Effect::Confusion),
+        Just(Effect::Sleep),
+        Just(Effect::Haste),
+    ];
+    (effect, 1..8i32).prop_map(|(effect, turns)| ActiveEffect { effect, turns, from: None })
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_effects_merge_tick_and_expire(gained in prop::collection::vec(any_effect(), 1..6), turns in 0..10i32) {
+        use crate::components::{register_all_components, CombatStats, Energy};
+        use crate::damage_system::DamageSystem;
+        use crate::initiative_system::InitiativeSystem;
+
+        let mut ecs = World::new();
+        register_all_components(&mut ecs);
+        ecs.insert(GameLog { entries: Vec::new() });
+        ecs.insert(RunState::Ticking);
+        let stats = CombatStats { max_hp: 1000, hp: 1000, defense: 0, power: 0 };
+        let victim = ecs
+            .create_entity()
+            .with(stats)
+            .with(Energy { speed: 100, energy: 0 })
+            .with(GainsEffect { pending: gained.clone() })
+            .build();
+        ecs.insert(victim);
+
+        EffectSystem {}.run_now(&ecs);
+        // Each kind once, as long and as strong as the best of its kind
+        let mut merged: Vec<ActiveEffect> = Vec::new();
+        for new in gained.iter() {
+            match merged.iter_mut().find(|m| m.effect.same_kind(&new.effect)) {
+                Some(m) => {
+                    m.turns = m.turns.max(new.turns);
+                    if let (Effect::Poison { damage: old }, Effect::Poison { damage }) = (&mut m.effect, new.effect) {
+                        *old = (*old).max(damage);
+                    }
+                }
+                None => merged.push(*new),
+            }
+        }
+        let active = |ecs: &World| ecs.read_storage::<StatusEffects>().get(victim).unwrap().active.clone();
+        prop_assert_eq!(active(&ecs), merged.clone());
+
+        for _ in 0..turns {
+            InitiativeSystem {}.run_now(&ecs);
+            StatusSystem {}.run_now(&ecs);
+            DamageSystem {}.run_now(&ecs);
+        }
+        let left: Vec<ActiveEffect> = merged
+            .iter()
+            .filter(|m| m.turns > turns)
+            .map(|m| ActiveEffect { turns: m.turns - turns, ..*m })
+            .collect();
+        prop_assert_eq!(active(&ecs), left);
+        let poisoned: i32 = merged
+            .iter()
+            .filter_map(|m| match m.effect {
+                Effect::Poison { damage } => Some(damage * m.turns.min(turns)),
+                _ => None,
+            })
+            .sum();
+        prop_assert_eq!(ecs.read_storage::<CombatStats>().get(victim).unwrap().hp, 1000 - poisoned);
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_poison_kills_are_credited_to_the_poisoner(damage in 1..5i32, hp in 1..20i32, max_hp in 20..40i32) {
+        use crate::components::{register_all_components, CombatStats, Energy, Experience};
+        use crate::damage_system::{delete_the_dead, xp_for_kill, DamageSystem};
+        use crate::initiative_system::InitiativeSystem;
+
+        let mut ecs = World::new();
+        register_all_components(&mut ecs);
+        ecs.insert(GameLog { entries: Vec::new() });
+        ecs.insert(RunState::Ticking);
+        let poisoner = ecs.create_entity().with(Experience { level: 1, xp: 0 }).build();
+        ecs.insert(poisoner);
+        let stats = CombatStats { max_hp, hp, defense: 0, power: 1 };
+        let dose = ActiveEffect { effect: Effect::Poison { damage }, turns: 40, from: Some(poisoner) };
+        let victim = ecs
+            .create_entity()
+            .with(stats.clone())
+            .with(Energy { speed: 100, energy: 0 })
+            .with(GainsEffect { pending: vec![dose] })
+            .build();
+
+        EffectSystem {}.run_now(&ecs);
+        while ecs.is_alive(victim) {
+            InitiativeSystem {}.run_now(&ecs);
+            StatusSystem {}.run_now(&ecs);
+            DamageSystem {}.run_now(&ecs);
+            delete_the_dead(&mut ecs);
+            ecs.maintain();
+        }
+        prop_assert_eq!(ecs.read_storage::<Experience>().get(poisoner).unwrap().xp, xp_for_kill(&stats));
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(test)]
+use proptest::prelude::*;
+use rltk::{Point, VirtualKeyCode};
+use specs::prelude::*;
+use std::ops::Range;
+
+use crate::{
+    components::{CombatStats, Position, Viewshed},
+    map::Map,
+};
+
+// What the player aims with while RunState::ShowTargeting is up. Only
+// lives as long as the targeting does.
+pub struct Targeting {
+    pub with: Entity,
+    pub range: i32,
+    // Into targets(), wrapped around when it runs past the end
+    pub current: usize,
+}
+
+// Close enough, with nothing opaque in the way
+pub fn can_fire(map: &Map, from: Point, to: Point, range: i32) -> bool {
+    rltk::DistanceAlg::Pythagoras.distance2d(from, to) <= range as f32 && map.line_of_fire(from, to)
+}
+
+// The tiles the player sees that a shot from `range` could reach
+pub fn valid_cells(ecs: &World, range: i32) -> Vec<Point> {
+    let player = *ecs.fetch::<Entity>();
+    let from = *ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let Some(viewshed) = viewsheds.get(player) else {
+        return Vec::new();
+    };
+    viewshed
+        .visible_tiles
+        .iter()
+        .copied()
+        .filter(|p| *p != from && can_fire(&map, from, *p, range))
+        .collect()
+}
+
+// Everyone the player could hit from here, nearest first. The order only
+// depends on the world, so replays pick the same targets.
+pub fn targets(ecs: &World, range: i32) -> Vec<(Entity, Point)> {
+    let player = *ecs.fetch::<Entity>();
+    let from = *ecs.fetch::<Point>();
+    let cells = valid_cells(ecs, range);
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let mut found: Vec<(Entity, Point)> = (&entities, &positions, &stats)
+        .join()
+        .filter(|(e, pos, s)| *e != player && s.hp > 0 && cells.contains(&pos.point))
+        .map(|(e, pos, _)| (e, pos.point))
+        .collect();
+    found.sort_by(|(a, pa), (b, pb)| {
+        let da = rltk::DistanceAlg::Pythagoras.distance2d(from, *pa);
+        let db = rltk::DistanceAlg::Pythagoras.distance2d(from, *pb);
+        da.total_cmp(&db).then(a.id().cmp(&b.id()))
+    });
+    found
+}
+
+// The number key that picks the nth target, as the overlay labels them
+pub fn target_key(n: usize) -> Option<VirtualKeyCode> {
+    const KEYS: [VirtualKeyCode; 9] = [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+    ];
+    KEYS.get(n).copied()
+}
+
+pub fn key_target(key: VirtualKeyCode) -> Option<usize> {
+    (0..9).find(|n| target_key(*n) == Some(key))
+}
+
+// The part of targets() a list with room for `rows` shows, moved along
+// so the picked one is always in it
+pub fn visible_targets(len: usize, picked: usize, rows: usize) -> Range<usize> {
+    let rows = len.min(rows);
+    let first = (picked + 1).saturating_sub(rows).min(len - rows);
+    first..first + rows
+}
+
+// Which target the mouse is over, so a click can be played as its key
+pub fn target_at(ecs: &World, range: i32, at: Point) -> Option<usize> {
+    targets(ecs, range).iter().position(|(_, p)| *p == at)
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_the_picked_target_is_always_listed(len in 1..60usize, picked in 0..60usize, rows in 1..20usize) {
+        let picked = picked % len;
+        let shown = visible_targets(len, picked, rows);
+        prop_assert!(shown.contains(&picked));
+        prop_assert_eq!(shown.len(), len.min(rows));
+        prop_assert!(shown.end <= len);
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+    #[test]
+    fn test_every_target_listed_can_be_shot(seed in any::<u64>(), dx in -7..=7i32, dy in -7..=7i32) {
+        use crate::action::{Action, ActionResolver, ActionResult};
+        use crate::components::{InBackpack, Ranged};
+        use crate::state::test_state;
+        use rltk::Algorithm2D;
+
+        let raws = crate::raws::test_raws();
+        let knife = raws.items.iter().find(|i| i.throwable.is_some()).unwrap().clone();
+        let dummy = raws.monsters[0].clone();
+        let mut gs = test_state(seed);
+        gs.run_until_input();
+        let player = *gs.ecs.fetch::<Entity>();
+        let from = *gs.ecs.fetch::<Point>();
+        let at = Point::new(from.x + dx, from.y + dy);
+        prop_assume!(at != from && gs.ecs.fetch::<Map>().in_bounds(at));
+
+        let target = dummy.with_point(at).build_entity(&mut gs.ecs);
+        let knife = knife.with_components(gs.ecs.create_entity()).with(InBackpack { owner: player }).build();
+        let range = gs.ecs.read_storage::<Ranged>().get(knife).unwrap().range;
+        let listed = targets(&gs.ecs, range).iter().any(|(e, _)| *e == target);
+        prop_assert_eq!(listed, valid_cells(&gs.ecs, range).contains(&at));
+
+        // The resolver doesn't care what the player sees, only that the
+        // line is clear, so it allows at least what the list offers
+        let result = gs.ecs.system_data::<ActionResolver>().resolve(player, Action::Shoot { with: knife, target });
+        if listed {
+            prop_assert_eq!(result, ActionResult::Done);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Error, Ident, LitChar, LitStr, Result, Token};
+
+use crate::spec_macros::push_error;
+
+enum TileArg {
+    Glyph(LitChar),
+    // The name of one of rltk's colour constants
+    Color(LitStr),
+    Blocks,
+    Opaque,
+}
+
+impl Parse for TileArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: Ident = input.parse()?;
+        match key.to_string().as_str() {
+            "glyph" => {
+                input.parse::<Token![=]>()?;
+                Ok(TileArg::Glyph(input.parse()?))
+            }
+            "color" => {
+                input.parse::<Token![=]>()?;
+                Ok(TileArg::Color(input.parse()?))
+            }
+            "blocks" => Ok(TileArg::Blocks),
+            "opaque" => Ok(TileArg::Opaque),
+            _ => Err(Error::new(
+                key.span(),
+                format!(
+                    "unknown tile argument `{}`, expected `glyph`, `color`, `blocks` or `opaque`",
+                    key
+                ),
+            )),
+        }
+    }
+}
+
+// "DARK_GREEN" becomes rltk::DARK_GREEN. A name rltk doesn't have is left
+// for the compiler to report, pointing at the string.
+fn colour_path(lit: &LitStr) -> Result<TokenStream> {
+    let name = lit.value();
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(Error::new(
+            lit.span(),
+            format!(
+                "{:?} isn't a colour name, use one of rltk's colour constants like \"YELLOW\"",
+                name
+            ),
+        ));
+    }
+    let ident = Ident::new(&name, lit.span());
+    Ok(quote_spanned! {lit.span()=> ::rltk::RGB::named(::rltk::#ident) })
+}
+
+// Implements crate::map::TileMeta from a #[tile(...)] on every variant:
+//
+//   #[derive(TileMeta)]
+//   enum TileType {
+//       #[tile(glyph = '#', color = "YELLOW", blocks)]
+//       Wall,
+//       #[tile(glyph = '.')]
+//       Floor,
+//   }
+//
+// `blocks` stops movement and sight, `opaque` only stops sight. Tiles
+// without a colour are white.
+pub fn impl_tile_macro(ast: &DeriveInput) -> Result<TokenStream> {
+    let name = &ast.ident;
+    let Data::Enum(data) = &ast.data else {
+        return Err(Error::new(ast.ident.span(), "TileMeta can only be derived for enums"));
+    };
+
+    let mut errors: Option<Error> = None;
+
+    let mut blocks = Vec::new();
+    let mut opaque = Vec::new();
+    let mut glyphs = Vec::new();
+    let mut colours = Vec::new();
+
+    for variant in data.variants.iter() {
+        let ident = &variant.ident;
+        let mut args = Vec::new();
+        let mut unreadable = false;
+        for attr in variant.attrs.iter().filter(|a| a.path.is_ident("tile")) {
+            match attr.parse_args_with(Punctuated::<TileArg, Token![,]>::parse_terminated) {
+                Ok(parsed) => args.extend(parsed),
+                Err(e) => {
+                    push_error(&mut errors, e);
+                    unreadable = true;
+                }
+            }
+        }
+
+        let mut glyph = None;
+        let mut colour = None;
+        let mut is_blocker = false;
+        let mut is_opaque = false;
+        for arg in args {
+            match arg {
+                TileArg::Glyph(c) if glyph.is_some() => push_error(
+                    &mut errors,
+                    Error::new(c.span(), "this tile already has a glyph"),
+                ),
+                TileArg::Glyph(c) => glyph = Some(c),
+                TileArg::Color(lit) if colour.is_some() => push_error(
+                    &mut errors,
+                    Error::new(lit.span(), "this tile already has a color"),
+                ),
+                TileArg::Color(lit) => match colour_path(&lit) {
+                    Ok(path) => colour = Some(path),
+                    Err(e) => push_error(&mut errors, e),
+                },
+                TileArg::Blocks => is_blocker = true,
+                TileArg::Opaque => is_opaque = true,
+            }
+        }
+
+        let Some(glyph) = glyph else {
+            // An attribute that didn't parse has been reported already
+            if !unreadable {
+                push_error(
+                    &mut errors,
+                    Error::new(ident.span(), format!("{} needs a #[tile(glyph = '...')]", ident)),
+                );
+            }
+            continue;
+        };
+        // Anything that blocks movement blocks sight too
+        let is_opaque = is_blocker || is_opaque;
+        let pattern = quote! { #name::#ident { .. } };
+        blocks.push(quote! { #pattern => #is_blocker, });
+        opaque.push(quote! { #pattern => #is_opaque, });
+        glyphs.push(quote! { #pattern => ::rltk::to_cp437(#glyph), });
+        let colour = colour.unwrap_or_else(|| quote! { ::rltk::RGB::named(::rltk::WHITE) });
+        colours.push(quote! { #pattern => #colour, });
+    }
+
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    Ok(quote! {
+        impl crate::map::TileMeta for #name {
+            fn is_blocker(&self) -> bool {
+                match self { #(#blocks)* }
+            }
+            fn is_opaque(&self) -> bool {
+                match self { #(#opaque)* }
+            }
+            fn glpyh(&self) -> ::rltk::FontCharType {
+                match self { #(#glyphs)* }
+            }
+            fn color(&self) -> ::rltk::RGB {
+                match self { #(#colours)* }
+            }
+        }
+    })
+}
@@ -1,6 +1,6 @@
 use specs::prelude::*;
 use crate::components::{Viewshed, Position, Player};
-use crate::map::{Map, xy_idx};
+use crate::map::Map;
 use rltk::{field_of_view};
 
 pub struct VisibilitySystem {}
@@ -29,7 +29,7 @@ impl<'a> System<'a> for VisibilitySystem {
             if let Some(_p) = p {
                 for t in map.visible_tiles.iter_mut() { *t= false;}
                 for vis in viewshed.visible_tiles.iter() {
-                    let idx = xy_idx(vis.x, vis.y);
+                    let idx = map.xy_idx(vis.x, vis.y);
                     map.revealed_tiles[idx] = true;
                     map.visible_tiles[idx] = true;
                 }
@@ -0,0 +1,8 @@
+// The derives have to explain themselves when they are used wrong, these
+// check what they say. Run with TRYBUILD=overwrite to accept new messages.
+#[test]
+fn derive_diagnostics() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass_*.rs");
+    t.compile_fail("tests/ui/fail_*.rs");
+}
@@ -0,0 +1,16 @@
+use rouge::FromSpec;
+use specs::prelude::*;
+use specs_derive::Component;
+
+#[derive(Component, Default)]
+struct Energy {
+    speed: i32,
+}
+
+#[derive(Clone, FromSpec)]
+#[spec(default = Energy)]
+struct Spec {
+    speed: i32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rouge::FromSpec;
+
+#[derive(Clone, FromSpec)]
+enum Spec {
+    Small,
+    Large,
+}
+
+fn main() {}
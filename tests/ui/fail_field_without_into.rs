@@ -0,0 +1,9 @@
+use rouge::FromSpec;
+
+#[derive(Clone, FromSpec)]
+struct Spec {
+    #[spec(field = speed)]
+    quickness: i32,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use rouge::FromSpec;
+use specs::prelude::*;
+use specs_derive::Component;
+
+#[derive(Component)]
+struct Position {
+    point: (i32, i32),
+}
+
+#[derive(Clone, FromSpec)]
+struct Spec {
+    #[spec(into = Position)]
+    pos: (i32, i32),
+}
+
+fn main() {}
@@ -0,0 +1,27 @@
+use rouge::TileMeta;
+
+mod map {
+    pub trait TileMeta {
+        fn is_blocker(&self) -> bool;
+        fn is_opaque(&self) -> bool;
+        fn glpyh(&self) -> rltk::FontCharType;
+        fn color(&self) -> rltk::RGB;
+    }
+}
+
+#[derive(TileMeta)]
+enum Tile {
+    #[tile(color = "YELLOW", blocks)]
+    Wall,
+    #[tile(glyph = '.', walkable)]
+    Floor,
+    #[tile(glyph = '~', color = "light blue")]
+    Water,
+}
+
+#[derive(TileMeta)]
+struct NotATile {
+    glyph: char,
+}
+
+fn main() {}
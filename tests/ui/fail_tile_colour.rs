@@ -0,0 +1,18 @@
+use rouge::TileMeta;
+
+mod map {
+    pub trait TileMeta {
+        fn is_blocker(&self) -> bool;
+        fn is_opaque(&self) -> bool;
+        fn glpyh(&self) -> rltk::FontCharType;
+        fn color(&self) -> rltk::RGB;
+    }
+}
+
+#[derive(TileMeta)]
+enum Tile {
+    #[tile(glyph = '#', color = "YELOW")]
+    Wall,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rouge::FromSpec;
+
+#[derive(Clone, FromSpec)]
+struct Spec(i32, i32);
+
+fn main() {}
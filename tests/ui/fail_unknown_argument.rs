@@ -0,0 +1,14 @@
+use rouge::FromSpec;
+
+struct Name {
+    name: String,
+}
+
+#[derive(Clone, FromSpec)]
+#[spec(wiht = Name { name: String::new() })]
+struct Spec {
+    #[spec(inot = Name)]
+    name: String,
+}
+
+fn main() {}
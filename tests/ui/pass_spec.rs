@@ -0,0 +1,52 @@
+use rouge::FromSpec;
+use specs::prelude::*;
+use specs_derive::Component;
+
+#[derive(Component, Debug, PartialEq)]
+struct Position {
+    point: (i32, i32),
+}
+
+#[derive(Component, Debug, Default, PartialEq)]
+struct Energy {
+    speed: i32,
+    energy: i32,
+}
+
+#[derive(Component, Clone, Debug, PartialEq)]
+struct Health {
+    hp: i32,
+}
+
+#[derive(Component, Debug)]
+struct Blocks {}
+
+#[derive(Clone, FromSpec)]
+#[spec(with = Blocks {}, default = Energy)]
+struct Spec {
+    #[spec(into = Position)]
+    point: (i32, i32),
+    #[spec(into = Energy, field = speed)]
+    quickness: i32,
+    #[spec(component)]
+    health: Option<Health>,
+}
+
+fn main() {
+    let mut ecs = World::new();
+    ecs.register::<Position>();
+    ecs.register::<Energy>();
+    ecs.register::<Health>();
+    ecs.register::<Blocks>();
+
+    let spec = Spec {
+        point: (1, 2),
+        quickness: 150,
+        health: Some(Health { hp: 3 }),
+    };
+    let e = spec.build_entity(&mut ecs);
+    assert_eq!(ecs.read_storage::<Position>().get(e), Some(&Position { point: (1, 2) }));
+    assert_eq!(ecs.read_storage::<Energy>().get(e), Some(&Energy { speed: 150, energy: 0 }));
+    assert_eq!(ecs.read_storage::<Health>().get(e), Some(&Health { hp: 3 }));
+    assert!(ecs.read_storage::<Blocks>().contains(e));
+}
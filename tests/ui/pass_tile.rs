@@ -0,0 +1,31 @@
+use rouge::TileMeta;
+
+mod map {
+    pub trait TileMeta {
+        fn is_blocker(&self) -> bool;
+        fn is_opaque(&self) -> bool;
+        fn glpyh(&self) -> rltk::FontCharType;
+        fn color(&self) -> rltk::RGB;
+    }
+}
+
+use map::TileMeta as _;
+
+#[derive(TileMeta)]
+enum Tile {
+    #[tile(glyph = '#', color = "DARK_GREEN", blocks)]
+    Wall,
+    #[tile(glyph = '~', color = "BLUE", opaque)]
+    Fog,
+    #[tile(glyph = '.')]
+    Floor,
+}
+
+fn main() {
+    assert!(Tile::Wall.is_blocker() && Tile::Wall.is_opaque());
+    assert!(!Tile::Fog.is_blocker() && Tile::Fog.is_opaque());
+    assert!(!Tile::Floor.is_blocker() && !Tile::Floor.is_opaque());
+    assert_eq!(Tile::Wall.glpyh(), rltk::to_cp437('#'));
+    assert_eq!(Tile::Fog.color(), rltk::RGB::named(rltk::BLUE));
+    assert_eq!(Tile::Floor.color(), rltk::RGB::named(rltk::WHITE));
+}